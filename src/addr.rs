@@ -0,0 +1,111 @@
+use crate::address::Address;
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use std::io::{self, Read, Write};
+
+// Maximum number of addresses in addr and addrv2 (MAX_ADDR_TO_SEND in Bitcoin Core)
+pub const MAX_ADDR_TO_SEND: u64 = 1000;
+
+/// Network address as relayed in addr, with the last time the node was seen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimestampedAddress {
+    pub time: u32,
+    pub address: Address,
+}
+
+impl TimestampedAddress {
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<TimestampedAddress, DeserializeError> {
+        encode::deserialize(bytes)
+    }
+}
+
+impl Encodable for TimestampedAddress {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(self.time.encode(writer)? + self.address.encode(writer)?)
+    }
+}
+
+impl Decodable for TimestampedAddress {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("TimestampedAddress", || {
+            Ok(Self {
+                time: Decodable::decode(reader)?,
+                address: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Addr {
+    pub addresses: Vec<TimestampedAddress>,
+}
+
+impl Addr {
+    pub fn new(addresses: Vec<TimestampedAddress>) -> Self {
+        Self { addresses }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Addr, DeserializeError> {
+        encode::deserialize_exact(bytes, "Addr")
+    }
+}
+
+impl Encodable for Addr {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.addresses.encode(writer)
+    }
+}
+
+impl Decodable for Addr {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Addr", || {
+            Ok(Self {
+                addresses: encode::decode_vec(reader, MAX_ADDR_TO_SEND)?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::services::ServiceFlags;
+
+    #[test]
+    fn test_addr_round_trip() {
+        let addr = Addr::new(vec![TimestampedAddress {
+            time: 1667596120,
+            address: Address {
+                services: ServiceFlags::NETWORK,
+                ip: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1],
+                port: 8333,
+            },
+        }]);
+        let raw = addr.serialize();
+
+        assert_eq!(raw.len(), 1 + 30);
+        assert_eq!(&raw[1..5], 1667596120u32.to_le_bytes());
+        assert_eq!(Addr::deserialize(&raw).unwrap(), addr);
+        assert!(Addr::deserialize(&raw[..30]).is_err());
+
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        let err = Addr::deserialize(&trailing).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+
+    #[test]
+    fn test_addr_too_many() {
+        assert!(Addr::deserialize(&[0xfd, 0xe9, 0x03]).is_err());
+    }
+}
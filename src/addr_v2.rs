@@ -0,0 +1,213 @@
+use crate::addr::MAX_ADDR_TO_SEND;
+use crate::encode::{self, CompactSize, Decodable, Encodable};
+use crate::error::{DeserializeError, ErrorKind};
+use crate::services::ServiceFlags;
+use std::io::{self, Read, Write};
+
+// Maximum size of an address in addrv2 (BIP155)
+pub const MAX_ADDRV2_SIZE: u64 = 512;
+
+/// Address of a node on one of the networks defined in BIP155.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkAddress {
+    Ipv4([u8; 4]),
+    Ipv6([u8; 16]),
+    // ed25519 public key of the onion service
+    TorV3([u8; 32]),
+    // SHA256 of the I2P destination
+    I2p([u8; 32]),
+    Cjdns([u8; 16]),
+    // Network we do not know (including the deprecated TorV2), kept to be relayed as is
+    Unknown { network_id: u8, address: Vec<u8> },
+}
+
+impl NetworkAddress {
+    pub fn network_id(&self) -> u8 {
+        match self {
+            NetworkAddress::Ipv4(_) => 1,
+            NetworkAddress::Ipv6(_) => 2,
+            NetworkAddress::TorV3(_) => 4,
+            NetworkAddress::I2p(_) => 5,
+            NetworkAddress::Cjdns(_) => 6,
+            NetworkAddress::Unknown { network_id, .. } => *network_id,
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            NetworkAddress::Ipv4(address) => address,
+            NetworkAddress::Ipv6(address) => address,
+            NetworkAddress::TorV3(address) => address,
+            NetworkAddress::I2p(address) => address,
+            NetworkAddress::Cjdns(address) => address,
+            NetworkAddress::Unknown { address, .. } => address,
+        }
+    }
+
+    /// Build the address from its network ID, known networks must have the expected length.
+    pub fn from_bytes(network_id: u8, address: Vec<u8>) -> Result<Self, DeserializeError> {
+        let invalid_length = || DeserializeError::new(ErrorKind::InvalidValue { field: "address" });
+
+        let result = match network_id {
+            1 => NetworkAddress::Ipv4(address[..].try_into().map_err(|_| invalid_length())?),
+            2 => NetworkAddress::Ipv6(address[..].try_into().map_err(|_| invalid_length())?),
+            4 => NetworkAddress::TorV3(address[..].try_into().map_err(|_| invalid_length())?),
+            5 => NetworkAddress::I2p(address[..].try_into().map_err(|_| invalid_length())?),
+            6 => NetworkAddress::Cjdns(address[..].try_into().map_err(|_| invalid_length())?),
+            _ => NetworkAddress::Unknown {
+                network_id,
+                address,
+            },
+        };
+
+        Ok(result)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrV2Entry {
+    pub time: u32,
+    pub services: ServiceFlags,
+    pub address: NetworkAddress,
+    pub port: u16,
+}
+
+impl AddrV2Entry {
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize_with_size(bytes: &[u8]) -> Result<(AddrV2Entry, u64), DeserializeError> {
+        encode::deserialize_with_size(bytes)
+    }
+}
+
+impl Encodable for AddrV2Entry {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.time.encode(writer)?;
+        size += CompactSize(self.services.0).encode(writer)?;
+        size += self.address.network_id().encode(writer)?;
+        size += encode::encode_var_bytes(self.address.as_bytes(), writer)?;
+        writer.write_all(&self.port.to_be_bytes())?;
+        Ok(size + 2)
+    }
+}
+
+impl Decodable for AddrV2Entry {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("AddrV2Entry", || {
+            let time = u32::decode(reader)?;
+            let services = ServiceFlags(encode::CompactSize::decode(reader)?.0);
+
+            let network_id = u8::decode(reader)?;
+            let buf = encode::read_var_bytes(reader, MAX_ADDRV2_SIZE)?;
+            let address = NetworkAddress::from_bytes(network_id, buf)?;
+
+            let port = u16::from_be_bytes(Decodable::decode(reader)?);
+
+            Ok(Self {
+                time,
+                services,
+                address,
+                port,
+            })
+        })
+    }
+}
+
+/// BIP155 `addrv2`, sent instead of `addr` to peers that sent us `sendaddrv2`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AddrV2 {
+    pub addresses: Vec<AddrV2Entry>,
+}
+
+impl AddrV2 {
+    pub fn new(addresses: Vec<AddrV2Entry>) -> Self {
+        Self { addresses }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<AddrV2, DeserializeError> {
+        encode::deserialize_exact(bytes, "AddrV2")
+    }
+}
+
+impl Encodable for AddrV2 {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.addresses.encode(writer)
+    }
+}
+
+impl Decodable for AddrV2 {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("AddrV2", || {
+            Ok(Self {
+                addresses: encode::decode_vec(reader, MAX_ADDR_TO_SEND)?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_addr_v2_serialize() {
+        let addr_v2 = AddrV2::new(vec![AddrV2Entry {
+            time: 0x5f000000,
+            services: ServiceFlags::NETWORK | ServiceFlags::WITNESS | ServiceFlags::NETWORK_LIMITED,
+            address: NetworkAddress::Ipv4([1, 2, 3, 4]),
+            port: 8333,
+        }]);
+
+        assert_eq!(
+            addr_v2.serialize(),
+            [1, 0, 0, 0, 0x5f, 0xfd, 0x09, 0x04, 1, 4, 1, 2, 3, 4, 0x20, 0x8d]
+        );
+    }
+
+    #[test]
+    fn test_addr_v2_round_trip() {
+        let addresses = [
+            NetworkAddress::Ipv4([1, 2, 3, 4]),
+            NetworkAddress::Ipv6([0x20; 16]),
+            NetworkAddress::TorV3([0xab; 32]),
+            NetworkAddress::I2p([0xcd; 32]),
+            NetworkAddress::Cjdns([0xfc; 16]),
+            NetworkAddress::Unknown {
+                network_id: 3,
+                address: vec![0x11; 10],
+            },
+        ];
+        let addr_v2 = AddrV2::new(
+            addresses
+                .into_iter()
+                .map(|address| AddrV2Entry {
+                    time: 1667596120,
+                    services: ServiceFlags::NETWORK,
+                    address,
+                    port: 8333,
+                })
+                .collect(),
+        );
+
+        assert_eq!(AddrV2::deserialize(&addr_v2.serialize()).unwrap(), addr_v2);
+    }
+
+    #[test]
+    fn test_addr_v2_invalid() {
+        // IPv4 address of 5 bytes
+        assert!(AddrV2::deserialize(&[1, 0, 0, 0, 0, 1, 1, 5, 1, 2, 3, 4, 5, 0, 0]).is_err());
+        // Address over 512 bytes
+        assert!(AddrV2::deserialize(&[1, 0, 0, 0, 0, 1, 9, 0xfd, 0x01, 0x02]).is_err());
+        // Truncated
+        assert!(AddrV2::deserialize(&[1, 0, 0, 0, 0, 1, 1, 4, 1, 2, 3, 4, 0]).is_err());
+        // Trailing byte
+        let err = AddrV2::deserialize(&[1, 0, 0, 0, 0, 1, 1, 4, 1, 2, 3, 4, 0, 0, 0]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+}
@@ -1,38 +1,131 @@
+use crate::encode::{self, Decodable, Encodable};
 use crate::error::DeserializeError;
-use std::io::{Cursor, Read};
+use crate::services::ServiceFlags;
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Default, PartialEq)]
 pub struct Address {
-    pub services: u64,
+    pub services: ServiceFlags,
+    // IPv6 address, IPv4 addresses are IPv4-mapped (::ffff:a.b.c.d)
     pub ip: [u8; 16],
     pub port: u16,
 }
 
 impl Address {
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.extend_from_slice(&self.services.to_le_bytes());
-        result.extend_from_slice(&self.ip);
-        result.extend_from_slice(&self.port.to_le_bytes());
+    pub fn new(socket_addr: &SocketAddr, services: ServiceFlags) -> Self {
+        let ip = match socket_addr.ip() {
+            IpAddr::V4(ip) => ip.to_ipv6_mapped(),
+            IpAddr::V6(ip) => ip,
+        };
+
+        Self {
+            services,
+            ip: ip.octets(),
+            port: socket_addr.port(),
+        }
+    }
+
+    /// IP address of the node, IPv4-mapped addresses are returned as IPv4.
+    pub fn ip_addr(&self) -> IpAddr {
+        let ip = Ipv6Addr::from(self.ip);
+        match ip.to_ipv4_mapped() {
+            Some(ip) => IpAddr::V4(ip),
+            None => IpAddr::V6(ip),
+        }
+    }
+
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip_addr(), self.port)
+    }
 
-        result
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Address, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize(bytes)
+    }
+}
+
+impl Encodable for Address {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.services.0.encode(writer)?;
+        size += self.ip.encode(writer)?;
+        // Port is in network byte order
+        writer.write_all(&self.port.to_be_bytes())?;
+        Ok(size + 2)
+    }
+}
+
+impl Decodable for Address {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Address", || {
+            let services = ServiceFlags(u64::decode(reader)?);
+            let ip = Decodable::decode(reader)?;
+            let port = u16::from_be_bytes(Decodable::decode(reader)?);
 
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let services = u64::from_le_bytes(buf);
+            Ok(Self { services, ip, port })
+        })
+    }
+}
 
-        let mut buf = [0u8; 16];
-        cur.read_exact(&mut buf)?;
-        let ip = buf;
+impl From<SocketAddr> for Address {
+    fn from(socket_addr: SocketAddr) -> Self {
+        Address::new(&socket_addr, ServiceFlags::NONE)
+    }
+}
+
+impl From<&Address> for SocketAddr {
+    fn from(address: &Address) -> Self {
+        address.socket_addr()
+    }
+}
+
+impl From<Address> for SocketAddr {
+    fn from(address: Address) -> Self {
+        address.socket_addr()
+    }
+}
+
+impl fmt::Display for Address {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.socket_addr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_address_port_big_endian() {
+        let address = Address::new(&"127.0.0.1:8333".parse().unwrap(), ServiceFlags::NETWORK);
+        let raw = address.serialize();
+
+        assert_eq!(
+            raw,
+            [
+                1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1, 0x20,
+                0x8d
+            ]
+        );
+        assert_eq!(Address::deserialize(&raw).unwrap(), address);
+    }
 
-        let mut buf = [0u8; 2];
-        cur.read_exact(&mut buf)?;
-        let port = u16::from_le_bytes(buf);
+    #[test]
+    fn test_address_socket_addr() {
+        let ipv4: SocketAddr = "1.2.3.4:22556".parse().unwrap();
+        let address = Address::from(ipv4);
+        assert_eq!(address.ip[10..], [255, 255, 1, 2, 3, 4]);
+        assert_eq!(SocketAddr::from(&address), ipv4);
+        assert_eq!(address.to_string(), "1.2.3.4:22556");
 
-        Ok(Self { services, ip, port })
+        let ipv6: SocketAddr = "[2001:db8::1]:8333".parse().unwrap();
+        let address = Address::from(ipv6);
+        let socket_addr: SocketAddr = address.clone().into();
+        assert_eq!(socket_addr, ipv6);
+        assert_eq!(address.to_string(), "[2001:db8::1]:8333");
     }
 }
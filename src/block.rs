@@ -1,208 +1,740 @@
-use crate::error::DeserializeError;
-use crate::tx::{Tx, TxIn, TxOut};
+use crate::encode::{self, Decodable, DecodableWithParams, Encodable};
+use crate::error::{DeserializeError, ValidationError};
+use crate::hash::{BlockHash, MerkleRoot, WitnessCommitment, WitnessMerkleRoot};
+use crate::merkle;
+use crate::network::{AuxPoWParams, ChainParams};
+use crate::pow::{self, U256};
+use crate::tx::Tx;
 use crate::utils;
-use std::io::{Cursor, Read};
-use varint::VarInt;
+use std::io::{self, Read, Write};
 
 const BLOCK_VERSION_AUXPOW_BIT: u32 = 0x100;
+// BIP9 version bits: the top 3 bits are 001 and the 29 others can each signal a deployment
+const VERSION_BITS_TOP_MASK: u32 = 0xE0000000;
+const VERSION_BITS_TOP_BITS: u32 = 0x20000000;
+pub const VERSION_BITS_NUM_BITS: u8 = 29;
+// OP_RETURN, push of 36 bytes and the BIP141 commitment header, followed by the 32 bytes commitment
+const WITNESS_COMMITMENT_HEADER: [u8; 6] = [0x6a, 0x24, 0xaa, 0x21, 0xa9, 0xed];
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Block {
-    pub version: u32,
+    pub header: BlockHeader,
     // auxpow header (to be compatible with Namecoin and Dogecoin)
     pub auxpow_header: Option<AuxPoWHeader>,
-    pub previous_hash: [u8; 32],
-    pub merkle_root: [u8; 32],
-    pub timestamp: u32,
-    pub bits: u32,
-    pub nonce: u32,
     pub transactions: Vec<Tx>,
 }
 
 impl Block {
-    pub fn hash(&self) -> [u8; 32] {
-        let block_header = &self.serialize_header();
-        utils::double_hash(block_header)
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
     }
 
-    pub fn serialize_header(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.extend(self.version.to_le_bytes());
-        result.extend(self.previous_hash);
-        result.extend(self.merkle_root);
-        result.extend(self.timestamp.to_le_bytes());
-        result.extend(self.bits.to_le_bytes());
-        result.extend(self.nonce.to_le_bytes());
-        result
+    /// Hash the proof of work is checked on: the parent block header for merged mined blocks,
+    /// the block header otherwise. Chains using another algorithm than sha256d (e.g. scrypt for
+    /// Litecoin and Dogecoin) have to hash the header themselves and use `pow::check_pow`.
+    pub fn pow_hash(&self) -> BlockHash {
+        match &self.auxpow_header {
+            Some(auxpow_header) => auxpow_header.parent_block.hash(),
+            None => self.hash(),
+        }
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.extend(self.version.to_le_bytes());
-        result.extend(self.previous_hash);
-        result.extend(self.merkle_root);
-        result.extend(self.timestamp.to_le_bytes());
-        result.extend(self.bits.to_le_bytes());
-        result.extend(self.nonce.to_le_bytes());
-        //TODO: serialize transactions
-
-        /*self.transactions
-        .iter()
-        .for_each(|t| {
-            result.extend(t.serialize());
-        });*/
-
-        result
-    }
-
-    pub fn deserialize(bytes: &[u8], auxpow_activated: bool) -> Result<Block, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
-
-        // Block headers
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let version = u32::from_le_bytes(buf);
-
-        let mut buf = [0u8; 32];
-        cur.read_exact(&mut buf)?;
-        let previous_hash = buf;
-
-        let mut buf = [0u8; 32];
-        cur.read_exact(&mut buf)?;
-        let merkle_root = buf;
-
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let timestamp = u32::from_le_bytes(buf);
-
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let bits = u32::from_le_bytes(buf);
-
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let nonce = u32::from_le_bytes(buf);
-
-        if auxpow_activated && (version & BLOCK_VERSION_AUXPOW_BIT) != 0 {
-            let (aux_power, size) = match AuxPoWHeader::deserialize_with_size(cur.split().1) {
-                Ok((aux_power, size)) => (aux_power, size),
-                Err(error) => {
-                    return Err(error);
+    pub fn check_pow(&self) -> Result<(), ValidationError> {
+        pow::check_pow(self.pow_hash().0, self.header.bits)
+    }
+
+    pub fn work(&self) -> U256 {
+        self.header.work()
+    }
+
+    pub fn compute_merkle_root(&self) -> MerkleRoot {
+        let txids: Vec<[u8; 32]> = self.transactions.iter().map(|tx| tx.txid().0).collect();
+        MerkleRoot(merkle::merkle_root(&txids))
+    }
+
+    /// Verify that the transactions match the header merkle root and that the list was not
+    /// mutated by duplicating transactions (CVE-2012-2459).
+    pub fn check_merkle_root(&self) -> Result<(), ValidationError> {
+        let txids: Vec<[u8; 32]> = self.transactions.iter().map(|tx| tx.txid().0).collect();
+        let (merkle_root, mutated) = merkle::merkle_root_with_mutation(&txids);
+
+        if MerkleRoot(merkle_root) != self.header.merkle_root {
+            return Err(ValidationError("Merkle root mismatch".to_owned()));
+        }
+        if mutated {
+            return Err(ValidationError(
+                "Duplicate transaction in block (mutated merkle tree)".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Merkle root of the wtxids, the coinbase wtxid being replaced by zeros (BIP141).
+    pub fn witness_root(&self) -> WitnessMerkleRoot {
+        let wtxids: Vec<[u8; 32]> = self
+            .transactions
+            .iter()
+            .enumerate()
+            .map(|(i, tx)| if i == 0 { [0u8; 32] } else { tx.wtxid().0 })
+            .collect();
+        WitnessMerkleRoot(merkle::merkle_root(&wtxids))
+    }
+
+    /// Witness commitment found in the coinbase outputs. When several outputs match the last one is used.
+    pub fn witness_commitment(&self) -> Option<WitnessCommitment> {
+        let coinbase = self.transactions.first()?;
+        coinbase
+            .tx_outs
+            .iter()
+            .rev()
+            .find(|tx_out| {
+                tx_out.pk_script.len() >= 38 && tx_out.pk_script[0..6] == WITNESS_COMMITMENT_HEADER
+            })
+            .map(|tx_out| WitnessCommitment(tx_out.pk_script[6..38].try_into().unwrap()))
+    }
+
+    /// Verify the coinbase witness commitment against the transactions witnesses. Blocks without
+    /// commitment must not contain witness data.
+    pub fn check_witness_commitment(&self) -> Result<(), ValidationError> {
+        let commitment = match self.witness_commitment() {
+            Some(commitment) => commitment,
+            None => {
+                if self.transactions.iter().any(|tx| tx.has_witness()) {
+                    return Err(ValidationError(
+                        "Unexpected witness data in block without commitment".to_owned(),
+                    ));
                 }
-            };
-            cur.set_position(cur.position() + size);
+                return Ok(());
+            }
+        };
+
+        // The coinbase witness holds the reserved value used in the commitment
+        let witness = match self.transactions.first().and_then(|tx| tx.tx_ins.first()) {
+            Some(tx_in) => &tx_in.witness,
+            None => {
+                return Err(ValidationError("Coinbase has no inputs".to_owned()));
+            }
+        };
+        if witness.len() != 1 || witness[0].len() != 32 {
+            return Err(ValidationError(
+                "Invalid witness reserved value in coinbase".to_owned(),
+            ));
         }
 
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)?;
-        cur.set_position(cur.position() + varint_size as u64);
+        let mut concat: Vec<u8> = vec![];
+        concat.extend(self.witness_root().as_bytes());
+        concat.extend(&witness[0]);
+        if WitnessCommitment(utils::double_hash(&concat)) != commitment {
+            return Err(ValidationError("Witness commitment mismatch".to_owned()));
+        }
 
-        let mut transactions: Vec<Tx> = vec![];
-        for _ in 0..count {
-            let (tx, tx_size) = Tx::deserialize_with_size(cur.split().1)?;
-            cur.set_position(cur.position() + tx_size);
+        Ok(())
+    }
 
-            transactions.push(tx);
+    /// Verify the merged mining proof of the block at `height`, if any, against its own hash.
+    /// Blocks before the AuxPoW start height must not carry one.
+    pub fn check_auxpow(&self, params: &AuxPoWParams, height: u32) -> Result<(), ValidationError> {
+        match &self.auxpow_header {
+            Some(_) if height < params.start_height => Err(ValidationError(
+                "AuxPoW before the merged mining start height".to_owned(),
+            )),
+            Some(auxpow_header) => auxpow_header.check(self.hash(), params.chain_id),
+            None => Ok(()),
         }
+    }
 
-        Ok(Self {
-            version,
-            auxpow_header: None,
-            previous_hash,
-            merkle_root,
-            timestamp,
-            bits,
-            nonce,
-            transactions,
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    /// Deserialize a block. On chains with merged mining the AuxPoW header is expected after the
+    /// block header when the AuxPoW version bit is set.
+    pub fn deserialize(bytes: &[u8], params: &ChainParams) -> Result<Block, DeserializeError> {
+        encode::deserialize_exact_with_params(bytes, params, "Block")
+    }
+}
+
+impl Encodable for Block {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.header.encode(writer)?;
+        if let Some(auxpow_header) = &self.auxpow_header {
+            size += auxpow_header.encode(writer)?;
+        }
+        size += self.transactions.encode(writer)?;
+        Ok(size)
+    }
+}
+
+impl DecodableWithParams for Block {
+    fn decode_with_params<R: Read + ?Sized>(
+        reader: &mut R,
+        params: &ChainParams,
+    ) -> Result<Self, DeserializeError> {
+        encode::within("Block", || {
+            let header = BlockHeader::decode(reader)?;
+
+            let mut auxpow_header = None;
+            if params.auxpow.is_some() && header.has_auxpow() {
+                auxpow_header = Some(AuxPoWHeader::decode(reader)?);
+            }
+
+            let transactions: Vec<Tx> = Vec::decode(reader)?;
+
+            Ok(Self {
+                header,
+                auxpow_header,
+                transactions,
+            })
         })
     }
 }
 
+/// The 80 bytes block header, what is hashed and what the headers message carries.
 #[derive(Debug, Clone, PartialEq)]
-pub struct AuxPoWHeader {
+pub struct BlockHeader {
     pub version: u32,
+    pub previous_hash: BlockHash,
+    pub merkle_root: MerkleRoot,
+    pub timestamp: u32,
+    pub bits: u32,
+    pub nonce: u32,
 }
 
-impl AuxPoWHeader {
-    pub fn deserialize_with_size(bytes: &[u8]) -> Result<(Self, u64), DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+impl BlockHeader {
+    pub const SIZE: usize = 80;
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let version = u32::from_le_bytes(buf);
+    pub fn hash(&self) -> BlockHash {
+        BlockHash(utils::double_hash(&self.serialize()))
+    }
 
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+    /// Work represented by this header, to be summed over headers to compare chains.
+    pub fn work(&self) -> U256 {
+        pow::work_from_compact(self.bits)
+    }
 
-        let mut tx_ins: Vec<TxIn> = vec![];
-        for _ in 0..count {
-            let (tx_in, size) = TxIn::deserialize_with_size(cur.split().1)?;
-            cur.set_position(cur.position() + size);
-        }
+    /// Whether the version is interpreted as BIP9 version bits.
+    pub fn uses_version_bits(&self) -> bool {
+        self.version & VERSION_BITS_TOP_MASK == VERSION_BITS_TOP_BITS
+    }
+
+    /// Whether the header signals readiness for the deployment using `bit` (BIP9).
+    pub fn signals_bit(&self, bit: u8) -> bool {
+        bit < VERSION_BITS_NUM_BITS && self.uses_version_bits() && self.version & (1 << bit) != 0
+    }
+
+    /// All the deployment bits signalled by the header.
+    pub fn signalled_bits(&self) -> Vec<u8> {
+        (0..VERSION_BITS_NUM_BITS)
+            .filter(|&bit| self.signals_bit(bit))
+            .collect()
+    }
+
+    /// Whether the version flags an AuxPoW header after the block header (on merged mined chains).
+    pub fn has_auxpow(&self) -> bool {
+        self.version & BLOCK_VERSION_AUXPOW_BIT != 0
+    }
+
+    /// Chain ID of merged mined chains, stored in the upper 16 bits of the version.
+    pub fn chain_id(&self) -> u32 {
+        self.version >> 16
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<BlockHeader, DeserializeError> {
+        encode::deserialize(bytes)
+    }
+}
+
+impl Encodable for BlockHeader {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.version.encode(writer)?;
+        size += self.previous_hash.encode(writer)?;
+        size += self.merkle_root.encode(writer)?;
+        size += self.timestamp.encode(writer)?;
+        size += self.bits.encode(writer)?;
+        size += self.nonce.encode(writer)?;
+        Ok(size)
+    }
+}
+
+impl Decodable for BlockHeader {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("BlockHeader", || {
+            Ok(Self {
+                version: Decodable::decode(reader)?,
+                previous_hash: Decodable::decode(reader)?,
+                merkle_root: Decodable::decode(reader)?,
+                timestamp: Decodable::decode(reader)?,
+                bits: Decodable::decode(reader)?,
+                nonce: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+// "\xfa\xbemm" magic placed in the parent coinbase before the chain merkle root
+const MERGED_MINING_HEADER: [u8; 4] = [0xfa, 0xbe, b'm', b'm'];
+const MAX_CHAIN_MERKLE_BRANCH: usize = 30;
 
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuxPoWHeader {
+    // coinbase of the parent block, it commits to the chain merkle root
+    pub coinbase_tx: Tx,
+    pub parent_hash: BlockHash,
+    // links the coinbase to the parent block merkle root
+    pub coinbase_branch: Vec<[u8; 32]>,
+    pub coinbase_index: u32,
+    // links the child block hash to the chain merkle root committed in the coinbase
+    pub blockchain_branch: Vec<[u8; 32]>,
+    pub blockchain_index: u32,
+    pub parent_block: BlockHeader,
+}
 
-        let mut tx_outs: Vec<TxOut> = vec![];
-        for _ in 0..count {
-            let (tx_out, size) = TxOut::deserialize_with_size(cur.split().1)?;
-            cur.set_position(cur.position() + size);
+impl AuxPoWHeader {
+    /// Verify that the parent block commits to `child_hash` for the chain `chain_id`.
+    /// The proof of work itself (on the parent block header) is not checked here.
+    pub fn check(&self, child_hash: BlockHash, chain_id: u32) -> Result<(), ValidationError> {
+        if self.coinbase_index != 0 {
+            return Err(ValidationError(
+                "AuxPoW is not a generate (coinbase index is not 0)".to_owned(),
+            ));
         }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let lock_time = u32::from_le_bytes(buf);
+        if self.parent_block.chain_id() == chain_id {
+            return Err(ValidationError(
+                "AuxPoW parent block has our chain ID".to_owned(),
+            ));
+        }
 
-        let mut buf = [0u8; 32];
-        cur.read_exact(&mut buf)?;
-        let parent_hash = buf;
+        if self.blockchain_branch.len() > MAX_CHAIN_MERKLE_BRANCH {
+            return Err(ValidationError(
+                "AuxPoW chain merkle branch too long".to_owned(),
+            ));
+        }
 
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+        // Check that the coinbase is part of the parent block
+        let coinbase_root = merkle::branch_root(
+            self.coinbase_tx.txid().0,
+            &self.coinbase_branch,
+            self.coinbase_index,
+        );
+        if MerkleRoot(coinbase_root) != self.parent_block.merkle_root {
+            return Err(ValidationError("AuxPoW merkle root incorrect".to_owned()));
+        }
 
-        for _ in 0..count {
-            let mut buf = [0u8; 32];
-            cur.read_exact(&mut buf)?;
-            let merkle_hash = buf;
+        // The chain merkle root is written in the coinbase script in reversed order
+        let mut root =
+            merkle::branch_root(child_hash.0, &self.blockchain_branch, self.blockchain_index);
+        root.reverse();
+
+        let script = match self.coinbase_tx.tx_ins.first() {
+            Some(tx_in) => &tx_in.signature_script,
+            None => {
+                return Err(ValidationError("AuxPoW coinbase has no inputs".to_owned()));
+            }
+        };
+
+        let root_position = match find(script, &root) {
+            Some(position) => position,
+            None => {
+                return Err(ValidationError(
+                    "AuxPoW missing chain merkle root in parent coinbase".to_owned(),
+                ));
+            }
+        };
+
+        match find(script, &MERGED_MINING_HEADER) {
+            Some(header_position) => {
+                if find(&script[header_position + 1..], &MERGED_MINING_HEADER).is_some() {
+                    return Err(ValidationError(
+                        "Multiple merged mining headers in coinbase".to_owned(),
+                    ));
+                }
+                if header_position + MERGED_MINING_HEADER.len() != root_position {
+                    return Err(ValidationError(
+                        "Merged mining header is not just before chain merkle root".to_owned(),
+                    ));
+                }
+            }
+            None => {
+                // Legacy format without the header, the root has to be at the start of the script
+                if root_position > 20 {
+                    return Err(ValidationError(
+                        "AuxPoW chain merkle root must start in the first 20 bytes of the parent coinbase".to_owned(),
+                    ));
+                }
+            }
         }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let bitmask = u32::from_le_bytes(buf);
+        let rest = &script[root_position + root.len()..];
+        if rest.len() < 8 {
+            return Err(ValidationError(
+                "AuxPoW missing chain merkle tree size and nonce in parent coinbase".to_owned(),
+            ));
+        }
 
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+        let size = u32::from_le_bytes(rest[0..4].try_into().unwrap());
+        let height = self.blockchain_branch.len() as u32;
+        if size != 1 << height {
+            return Err(ValidationError(
+                "AuxPoW merkle branch size does not match parent coinbase".to_owned(),
+            ));
+        }
 
-        for _ in 0..count {
-            let mut buf = [0u8; 32];
-            cur.read_exact(&mut buf)?;
-            let merkle_hash = buf;
+        let nonce = u32::from_le_bytes(rest[4..8].try_into().unwrap());
+        if self.blockchain_index != expected_index(nonce, chain_id, height) {
+            return Err(ValidationError("AuxPoW wrong index".to_owned()));
         }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let bitmask = u32::from_le_bytes(buf);
+        Ok(())
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize_with_size(bytes: &[u8]) -> Result<(Self, u64), DeserializeError> {
+        encode::deserialize_with_size(bytes)
+    }
+}
 
-        let mut buf = [0u8; 80];
-        cur.read_exact(&mut buf)?;
+impl Encodable for AuxPoWHeader {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.coinbase_tx.encode(writer)?;
+        size += self.parent_hash.encode(writer)?;
+        size += self.coinbase_branch.encode(writer)?;
+        size += self.coinbase_index.encode(writer)?;
+        size += self.blockchain_branch.encode(writer)?;
+        size += self.blockchain_index.encode(writer)?;
+        size += self.parent_block.encode(writer)?;
+        Ok(size)
+    }
+}
 
-        Ok((Self { version }, cur.position()))
+impl Decodable for AuxPoWHeader {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("AuxPoWHeader", || {
+            Ok(Self {
+                coinbase_tx: Decodable::decode(reader)?,
+                parent_hash: Decodable::decode(reader)?,
+                coinbase_branch: Decodable::decode(reader)?,
+                coinbase_index: Decodable::decode(reader)?,
+                blockchain_branch: Decodable::decode(reader)?,
+                blockchain_index: Decodable::decode(reader)?,
+                parent_block: Decodable::decode(reader)?,
+            })
+        })
     }
 }
 
+// Position in the chain merkle tree a chain is allowed to use (same pseudo random generator as Namecoin)
+fn expected_index(nonce: u32, chain_id: u32, height: u32) -> u32 {
+    let mut rand = nonce;
+    rand = rand.wrapping_mul(1103515245).wrapping_add(12345);
+    rand = rand.wrapping_add(chain_id);
+    rand = rand.wrapping_mul(1103515245).wrapping_add(12345);
+
+    rand % (1 << height)
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::error::ErrorKind;
+    use crate::hash::Txid;
+    use crate::network::Network;
+    use crate::tx::{Outpoint, TxIn, TxOut};
     use std::fs;
 
+    const CHAIN_ID: u32 = 0x62;
+
+    fn auxpow_for(
+        child_hash: BlockHash,
+        blockchain_branch: Vec<[u8; 32]>,
+        nonce: u32,
+    ) -> AuxPoWHeader {
+        let height = blockchain_branch.len() as u32;
+        let blockchain_index = expected_index(nonce, CHAIN_ID, height);
+        let mut root = merkle::branch_root(child_hash.0, &blockchain_branch, blockchain_index);
+        root.reverse();
+
+        let mut signature_script = vec![0x03, 0x01, 0x02, 0x03];
+        signature_script.extend(MERGED_MINING_HEADER);
+        signature_script.extend(root);
+        signature_script.extend((1u32 << height).to_le_bytes());
+        signature_script.extend(nonce.to_le_bytes());
+
+        let coinbase_tx = Tx {
+            version: 1,
+            tx_ins: vec![TxIn {
+                previous_output: Outpoint {
+                    previous_hash: Txid::default(),
+                    index: 0xffffffff,
+                },
+                signature_script,
+                sequence: 0xffffffff,
+                witness: vec![],
+            }],
+            tx_outs: vec![TxOut {
+                value: 50,
+                pk_script: vec![0x51],
+            }],
+            lock_time: 0,
+        };
+
+        let parent_block = BlockHeader {
+            version: 2,
+            previous_hash: BlockHash::default(),
+            merkle_root: MerkleRoot(coinbase_tx.txid().0),
+            timestamp: 0,
+            bits: 0x1d00ffff,
+            nonce: 0,
+        };
+
+        AuxPoWHeader {
+            coinbase_tx,
+            parent_hash: BlockHash::default(),
+            coinbase_branch: vec![],
+            coinbase_index: 0,
+            blockchain_branch,
+            blockchain_index,
+            parent_block,
+        }
+    }
+
     #[test]
     fn test_block_deserialize() {
         let f = fs::read("./raw_50057.bin").unwrap();
 
-        let block = Block::deserialize(&f, false).expect("should deserialize raw block");
+        let block = Block::deserialize(&f, Network::Dogecoin.params())
+            .expect("should deserialize raw block");
+
+        assert!(block.auxpow_header.is_none());
+        assert_eq!(block.transactions.len(), 2213);
+    }
+
+    #[test]
+    fn test_block_deserialize_error_context() {
+        let f = fs::read("./raw_50057.bin").unwrap();
+        let block = Block::deserialize(&f, Network::Dogecoin.params()).unwrap();
+
+        // Make the signature script length of the first input of tx #37 announce 0x30XX bytes
+        let tx_start: usize = BlockHeader::SIZE
+            + 3
+            + block.transactions[..37]
+                .iter()
+                .map(|tx| tx.serialize().len())
+                .sum::<usize>();
+        let script_length = tx_start + 4 + 1 + 36;
+        let mut corrupted = f.clone();
+        corrupted[script_length] = 0xfd;
+
+        let err = Block::deserialize(&corrupted, Network::Dogecoin.params()).unwrap_err();
+        assert!(matches!(
+            err.kind,
+            ErrorKind::OversizedVector { max: 10_000, .. }
+        ));
+        // Detected once the 3 bytes varint is read
+        let offset = script_length + 3;
+        assert_eq!(err.offset, offset as u64);
+        assert!(err
+            .to_string()
+            .ends_with(&format!("at byte {offset} in Block > Tx #37 > TxIn #0")));
+    }
+
+    #[test]
+    fn test_block_serialize() {
+        let f = fs::read("./raw_50057.bin").unwrap();
+
+        let block = Block::deserialize(&f, Network::Dogecoin.params()).unwrap();
+
+        assert_eq!(block.serialize(), f);
+
+        let mut trailing = f.clone();
+        trailing.push(0);
+        let err = Block::deserialize(&trailing, Network::Dogecoin.params()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+
+    #[test]
+    fn test_auxpow_block_round_trip() {
+        let mut block = Block::deserialize(
+            &fs::read("./raw_50057.bin").unwrap(),
+            Network::Dogecoin.params(),
+        )
+        .unwrap();
+        block.header.version |= BLOCK_VERSION_AUXPOW_BIT;
+        block.auxpow_header = Some(auxpow_for(block.hash(), vec![[1; 32]], 7));
+        block.transactions.truncate(3);
+        let raw = block.serialize();
+
+        let deserialized = Block::deserialize(&raw, Network::Dogecoin.params()).unwrap();
+
+        assert_eq!(deserialized, block);
+        assert_eq!(deserialized.serialize(), raw);
+        let params = AuxPoWParams {
+            chain_id: CHAIN_ID,
+            start_height: 50000,
+        };
+        assert!(deserialized.check_auxpow(&params, 50057).is_ok());
+        assert!(deserialized.check_auxpow(&params, 49999).is_err());
+        assert_eq!(
+            deserialized.pow_hash(),
+            block.auxpow_header.unwrap().parent_block.hash()
+        );
+    }
+
+    #[test]
+    fn test_block_check_pow() {
+        // Bitcoin genesis block header
+        let mut block = Block {
+            header: BlockHeader {
+                version: 1,
+                previous_hash: BlockHash::default(),
+                merkle_root: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b"
+                    .parse()
+                    .unwrap(),
+                timestamp: 1231006505,
+                bits: 0x1d00ffff,
+                nonce: 2083236893,
+            },
+            auxpow_header: None,
+            transactions: vec![],
+        };
+
+        assert_eq!(
+            block.hash().to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+        assert!(block.check_pow().is_ok());
+        assert_eq!(block.work(), U256::from_u64(0x100010001));
+
+        block.header.nonce += 1;
+        assert!(block.check_pow().is_err());
+    }
+
+    #[test]
+    fn test_block_header_version_bits() {
+        let f = fs::read("./raw_50057.bin").unwrap();
+        let mut header = BlockHeader::deserialize(&f[..BlockHeader::SIZE]).unwrap();
+
+        assert_eq!(header.version, 2);
+        assert!(!header.uses_version_bits());
+        assert!(!header.signals_bit(1));
+
+        header.version = 0x20000012;
+        assert!(header.uses_version_bits());
+        assert!(header.signals_bit(1));
+        assert!(!header.signals_bit(2));
+        assert_eq!(header.signalled_bits(), vec![1, 4]);
+
+        // Dogecoin AuxPoW version with the chain ID in the upper bits
+        header.version = 0x00620102;
+        assert!(!header.uses_version_bits());
+        assert!(header.has_auxpow());
+        assert_eq!(header.chain_id(), 0x62);
+    }
+
+    #[test]
+    fn test_block_check_merkle_root() {
+        let mut block = Block::deserialize(
+            &fs::read("./raw_50057.bin").unwrap(),
+            Network::Dogecoin.params(),
+        )
+        .unwrap();
+
+        assert!(block.check_merkle_root().is_ok());
+        assert!(block.check_witness_commitment().is_ok());
+
+        block.transactions.truncate(3);
+        block.header.merkle_root = block.compute_merkle_root();
+        assert!(block.check_merkle_root().is_ok());
+
+        // Same merkle root with the last transaction duplicated
+        block.transactions.push(block.transactions[2].clone());
+        assert_eq!(block.compute_merkle_root(), block.header.merkle_root);
+        assert!(block.check_merkle_root().is_err());
+
+        block.transactions.pop();
+        block.transactions.pop();
+        assert!(block.check_merkle_root().is_err());
+    }
+
+    #[test]
+    fn test_block_check_witness_commitment() {
+        let mut block = Block::deserialize(
+            &fs::read("./raw_50057.bin").unwrap(),
+            Network::Dogecoin.params(),
+        )
+        .unwrap();
+        block.transactions.truncate(1);
+        // BIP143 native P2WPKH example
+        block.transactions.push(Tx::deserialize(&hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap()).unwrap());
+
+        // Witness data without commitment
+        assert!(block.check_witness_commitment().is_err());
+
+        block.transactions[0].tx_ins[0].witness = vec![vec![0; 32]];
+        let mut concat = block.witness_root().0.to_vec();
+        concat.extend([0; 32]);
+        let mut pk_script = WITNESS_COMMITMENT_HEADER.to_vec();
+        pk_script.extend(utils::double_hash(&concat));
+        block.transactions[0].tx_outs.push(TxOut {
+            value: 0,
+            pk_script,
+        });
+        assert!(block.check_witness_commitment().is_ok());
+
+        block.transactions[1].tx_ins[1].witness[0][0] ^= 1;
+        assert!(block.check_witness_commitment().is_err());
+
+        // Malformed coinbase without inputs
+        block.transactions[0].tx_ins.clear();
+        assert!(block.check_witness_commitment().is_err());
+    }
+
+    #[test]
+    fn test_auxpow_header_round_trip() {
+        let auxpow_header = auxpow_for(BlockHash([7; 32]), vec![[1; 32], [2; 32]], 42);
+        let raw = auxpow_header.serialize();
+
+        let (deserialized, size) = AuxPoWHeader::deserialize_with_size(&raw).unwrap();
+
+        assert_eq!(size, raw.len() as u64);
+        assert_eq!(deserialized, auxpow_header);
+    }
+
+    #[test]
+    fn test_auxpow_header_check() {
+        let child_hash = BlockHash([7; 32]);
+
+        assert!(auxpow_for(child_hash, vec![], 0)
+            .check(child_hash, CHAIN_ID)
+            .is_ok());
+        let auxpow_header = auxpow_for(child_hash, vec![[1; 32], [2; 32], [3; 32]], 1234);
+        assert!(auxpow_header.check(child_hash, CHAIN_ID).is_ok());
+
+        // Commits to another block
+        assert!(auxpow_header.check(BlockHash([8; 32]), CHAIN_ID).is_err());
+
+        // Slot in the chain merkle tree does not match the chain ID
+        let mut wrong_index = auxpow_header.clone();
+        wrong_index.blockchain_index ^= 1;
+        assert!(wrong_index.check(child_hash, CHAIN_ID).is_err());
+
+        // Coinbase not part of the parent block
+        let mut wrong_parent = auxpow_header.clone();
+        wrong_parent.parent_block.merkle_root.0[0] ^= 1;
+        assert!(wrong_parent.check(child_hash, CHAIN_ID).is_err());
     }
 }
@@ -0,0 +1,212 @@
+use crate::error::MessageError;
+use crate::message::{Message, MessageHeader, HEADER_SIZE};
+use crate::network::ChainParams;
+use std::io::Read;
+
+/// Incremental message framing for data received in arbitrary chunks (e.g. from a socket).
+/// Bytes are added with `feed` and complete messages are taken out with `decode`. Garbage in
+/// front of a message is skipped by looking for the magic bytes.
+#[derive(Debug, Clone)]
+pub struct MessageDecoder {
+    magic_bytes: [u8; 4],
+    buffer: Vec<u8>,
+}
+
+impl MessageDecoder {
+    pub fn new(params: &ChainParams) -> Self {
+        Self {
+            magic_bytes: params.magic_bytes,
+            buffer: vec![],
+        }
+    }
+
+    pub fn feed(&mut self, bytes: &[u8]) {
+        self.buffer.extend_from_slice(bytes);
+        self.resync();
+    }
+
+    /// Minimum number of bytes to feed before the next message can be decoded. Zero when `decode`
+    /// has a message or an error (e.g. an invalid header) to return.
+    pub fn bytes_needed(&self) -> usize {
+        if self.buffer.len() < HEADER_SIZE {
+            return HEADER_SIZE - self.buffer.len();
+        }
+
+        // The size of a valid header is bounded by `MAX_PAYLOAD_SIZE`
+        match MessageHeader::deserialize(&self.buffer[..HEADER_SIZE]) {
+            Ok(header) => (HEADER_SIZE + header.size as usize).saturating_sub(self.buffer.len()),
+            Err(_) => 0,
+        }
+    }
+
+    /// Number of bytes received but not decoded yet.
+    pub fn buffered(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Take the next complete message out of the buffer. Returns `Ok(None)` when more bytes are
+    /// needed. After an error the faulty header (or message for a bad checksum) is dropped so
+    /// decoding can go on with the next message.
+    pub fn decode(&mut self) -> Result<Option<Message>, MessageError> {
+        if self.buffer.len() < HEADER_SIZE {
+            return Ok(None);
+        }
+
+        let header = match MessageHeader::deserialize(&self.buffer[..HEADER_SIZE]) {
+            Ok(header) => header,
+            Err(error) => {
+                // Skip the magic bytes so we look for the next message
+                self.buffer.drain(..1);
+                self.resync();
+                return Err(error);
+            }
+        };
+
+        let end = HEADER_SIZE + header.size as usize;
+        if self.buffer.len() < end {
+            return Ok(None);
+        }
+
+        let payload = self.buffer[HEADER_SIZE..end].to_vec();
+        self.buffer.drain(..end);
+        self.resync();
+
+        header.with_payload(payload).map(Some)
+    }
+
+    // Drop everything before the magic bytes. When they are not found, only keep the last bytes
+    // which could be the beginning of the magic bytes.
+    fn resync(&mut self) {
+        let start = match self
+            .buffer
+            .windows(4)
+            .position(|window| window == self.magic_bytes)
+        {
+            Some(position) => position,
+            None => self.buffer.len().saturating_sub(3),
+        };
+        self.buffer.drain(..start);
+    }
+}
+
+/// Read the next message from a blocking reader, skipping garbage before the magic bytes.
+/// The payload buffer grows with the data actually received, up to the (bounded) size declared in the header.
+pub fn read_message<R: Read>(
+    reader: &mut R,
+    params: &ChainParams,
+) -> Result<Message, MessageError> {
+    let mut header = [0u8; HEADER_SIZE];
+    reader.read_exact(&mut header[..4])?;
+    while header[..4] != params.magic_bytes {
+        header.copy_within(1..4, 0);
+        reader.read_exact(&mut header[3..4])?;
+    }
+    reader.read_exact(&mut header[4..])?;
+    let header = MessageHeader::deserialize(&header)?;
+
+    let mut payload: Vec<u8> = vec![];
+    reader.take(header.size as u64).read_to_end(&mut payload)?;
+
+    header.with_payload(payload)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::{DeserializeError, ErrorKind};
+    use crate::network::Network;
+    use std::io::Cursor;
+
+    fn params() -> &'static ChainParams {
+        Network::DogecoinTestnet.params()
+    }
+
+    #[test]
+    fn test_decode_partial_input() {
+        let ping = Message::new(params(), "ping".to_string(), vec![1; 8]);
+        let bytes = ping.serialize();
+        let mut decoder = MessageDecoder::new(params());
+
+        assert_eq!(decoder.bytes_needed(), HEADER_SIZE);
+        for (i, byte) in bytes.iter().enumerate() {
+            assert_eq!(decoder.decode().unwrap(), None);
+            if i < HEADER_SIZE {
+                assert_eq!(decoder.bytes_needed(), HEADER_SIZE - i);
+            } else {
+                assert_eq!(decoder.bytes_needed(), bytes.len() - i);
+            }
+            decoder.feed(&[*byte]);
+        }
+
+        assert_eq!(decoder.decode().unwrap(), Some(ping));
+        assert_eq!(decoder.buffered(), 0);
+    }
+
+    #[test]
+    fn test_decode_resync() {
+        let verack = Message::new(params(), "verack".to_string(), vec![]);
+        let ping = Message::new(params(), "ping".to_string(), vec![1; 8]);
+        let mut bytes = vec![0xFC, 0xC1, 1, 2, 3];
+        bytes.extend(verack.serialize());
+        bytes.extend([0xFC, 0xC1]);
+        bytes.extend(ping.serialize());
+        let mut decoder = MessageDecoder::new(params());
+
+        decoder.feed(&bytes);
+
+        assert_eq!(decoder.decode().unwrap(), Some(verack));
+        assert_eq!(decoder.decode().unwrap(), Some(ping));
+        assert_eq!(decoder.decode().unwrap(), None);
+    }
+
+    #[test]
+    fn test_decode_invalid_message() {
+        let verack = Message::new(params(), "verack".to_string(), vec![]);
+        let mut ping = Message::new(params(), "ping".to_string(), vec![1; 8]);
+        ping.checksum = [0; 4];
+        let mut bytes = ping.serialize();
+        bytes.extend(verack.serialize());
+        let mut decoder = MessageDecoder::new(params());
+
+        decoder.feed(&bytes);
+
+        assert!(matches!(
+            decoder.decode(),
+            Err(MessageError::Deserialize(DeserializeError {
+                kind: ErrorKind::InvalidChecksum { .. },
+                ..
+            }))
+        ));
+        assert_eq!(decoder.decode().unwrap(), Some(verack));
+    }
+
+    #[test]
+    fn test_decode_oversized_payload() {
+        let mut bytes = Message::new(params(), "block".to_string(), vec![]).serialize();
+        bytes[16..20].copy_from_slice(&u32::MAX.to_le_bytes());
+        let mut decoder = MessageDecoder::new(params());
+
+        decoder.feed(&bytes);
+
+        assert_eq!(decoder.bytes_needed(), 0);
+        assert!(matches!(
+            decoder.decode(),
+            Err(MessageError::OversizedPayload(_))
+        ));
+        assert_eq!(decoder.decode().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_message() {
+        let verack = Message::new(params(), "verack".to_string(), vec![]);
+        let ping = Message::new(params(), "ping".to_string(), vec![1; 8]);
+        let mut bytes = vec![0, 0xFC, 0xC1];
+        bytes.extend(verack.serialize());
+        bytes.extend(ping.serialize());
+        let mut cur = Cursor::new(bytes);
+
+        assert_eq!(read_message(&mut cur, params()).unwrap(), verack);
+        assert_eq!(read_message(&mut cur, params()).unwrap(), ping);
+        assert!(read_message(&mut cur, params()).is_err());
+    }
+}
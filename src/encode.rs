@@ -0,0 +1,461 @@
+use crate::error::{DeserializeError, ErrorKind};
+use crate::network::ChainParams;
+use std::io::{self, Read, Write};
+use std::mem::size_of;
+
+/// Wire encoding of a type.
+pub trait Encodable {
+    /// Write the encoding into `writer` and return the number of bytes written.
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize>;
+}
+
+/// Wire decoding of a type. Errors are located by `deserialize`, which knows how many bytes
+/// were read from the start of the input.
+pub trait Decodable: Sized {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError>;
+}
+
+/// Decoding of types depending on the chain, e.g. blocks carrying an AuxPoW header on merged
+/// mined chains.
+pub trait DecodableWithParams: Sized {
+    fn decode_with_params<R: Read + ?Sized>(
+        reader: &mut R,
+        params: &ChainParams,
+    ) -> Result<Self, DeserializeError>;
+}
+
+pub fn serialize<T: Encodable + ?Sized>(value: &T) -> Vec<u8> {
+    let mut result: Vec<u8> = vec![];
+    // Writing into a Vec cannot fail
+    value.encode(&mut result).unwrap();
+    result
+}
+
+pub fn deserialize<T: Decodable>(bytes: &[u8]) -> Result<T, DeserializeError> {
+    Ok(deserialize_with_size(bytes)?.0)
+}
+
+/// Decode a value from the start of `bytes` and return it with the number of bytes read.
+pub fn deserialize_with_size<T: Decodable>(bytes: &[u8]) -> Result<(T, u64), DeserializeError> {
+    let mut reader = Reader::new(bytes);
+    let value = reader.decode()?;
+    Ok((value, reader.position()))
+}
+
+/// Same as `deserialize` for messages that must use the whole payload.
+pub(crate) fn deserialize_exact<T: Decodable>(
+    bytes: &[u8],
+    type_name: &'static str,
+) -> Result<T, DeserializeError> {
+    let mut reader = Reader::new(bytes);
+    let value = reader.decode()?;
+    check_trailing_bytes(&reader, type_name)?;
+    Ok(value)
+}
+
+/// Same as `deserialize_exact` for types depending on the chain.
+pub(crate) fn deserialize_exact_with_params<T: DecodableWithParams>(
+    bytes: &[u8],
+    params: &ChainParams,
+    type_name: &'static str,
+) -> Result<T, DeserializeError> {
+    let mut reader = Reader::new(bytes);
+    let value = reader.decode_with_params(params)?;
+    check_trailing_bytes(&reader, type_name)?;
+    Ok(value)
+}
+
+fn check_trailing_bytes(
+    reader: &Reader<&[u8]>,
+    type_name: &'static str,
+) -> Result<(), DeserializeError> {
+    let trailing = reader.get_ref().len() as u64;
+    if trailing != 0 {
+        return Err(DeserializeError::new(ErrorKind::TrailingBytes(trailing))
+            .locate(reader.position())
+            .within(type_name));
+    }
+
+    Ok(())
+}
+
+/// Wrap a reader to count the bytes read from it, which is where decoding errors are located.
+/// Values can be decoded one after the other from a stream without buffering it.
+#[derive(Debug)]
+pub struct Reader<R> {
+    inner: R,
+    position: u64,
+}
+
+impl<R: Read> Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, position: 0 }
+    }
+
+    /// Number of bytes read so far.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Decode the next value, errors are located from the start of the stream.
+    pub fn decode<T: Decodable>(&mut self) -> Result<T, DeserializeError> {
+        T::decode(self).map_err(|e| e.locate(self.position))
+    }
+
+    pub fn decode_with_params<T: DecodableWithParams>(
+        &mut self,
+        params: &ChainParams,
+    ) -> Result<T, DeserializeError> {
+        T::decode_with_params(self, params).map_err(|e| e.locate(self.position))
+    }
+}
+
+impl<R: Read> Read for Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let size = self.inner.read(buf)?;
+        self.position += size as u64;
+        Ok(size)
+    }
+}
+
+// Add `type_name` to the context of the errors raised while decoding it
+pub(crate) fn within<T>(
+    type_name: &'static str,
+    decode: impl FnOnce() -> Result<T, DeserializeError>,
+) -> Result<T, DeserializeError> {
+    decode().map_err(|e| e.within(type_name))
+}
+
+/// Variable length integer used for counts and lengths (CompactSize in Bitcoin Core): one byte
+/// below 0xfd, otherwise a 0xfd, 0xfe or 0xff marker followed by a 2, 4 or 8 bytes integer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CompactSize(pub u64);
+
+impl CompactSize {
+    /// Number of bytes of the encoding, values always use the smallest one.
+    pub fn size(&self) -> usize {
+        match self.0 {
+            0..=0xfc => 1,
+            0xfd..=0xffff => 3,
+            0x10000..=0xffff_ffff => 5,
+            _ => 9,
+        }
+    }
+}
+
+impl From<u64> for CompactSize {
+    fn from(value: u64) -> Self {
+        CompactSize(value)
+    }
+}
+
+impl From<CompactSize> for u64 {
+    fn from(value: CompactSize) -> Self {
+        value.0
+    }
+}
+
+impl Encodable for CompactSize {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        match self.size() {
+            1 => (self.0 as u8).encode(writer),
+            3 => Ok(0xfdu8.encode(writer)? + (self.0 as u16).encode(writer)?),
+            5 => Ok(0xfeu8.encode(writer)? + (self.0 as u32).encode(writer)?),
+            _ => Ok(0xffu8.encode(writer)? + self.0.encode(writer)?),
+        }
+    }
+}
+
+/// Like Bitcoin Core, a value not using the smallest encoding is rejected: the same value would
+/// otherwise have several encodings, e.g. 5 as `0xfd 0x05 0x00`.
+impl Decodable for CompactSize {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        let (value, size) = match u8::decode(reader)? {
+            0xfd => (u16::decode(reader)? as u64, 3),
+            0xfe => (u32::decode(reader)? as u64, 5),
+            0xff => (u64::decode(reader)?, 9),
+            x => (x as u64, 1),
+        };
+
+        let value = CompactSize(value);
+        if value.size() != size {
+            return Err(DeserializeError::new(ErrorKind::NonCanonicalVarInt));
+        }
+
+        Ok(value)
+    }
+}
+
+// Read the number of elements of a list, rejecting counts over the protocol maximum
+pub(crate) fn read_count<R: Read + ?Sized>(
+    reader: &mut R,
+    max_count: u64,
+) -> Result<u64, DeserializeError> {
+    let count = CompactSize::decode(reader)?.0;
+    if count > max_count {
+        return Err(DeserializeError::new(ErrorKind::OversizedVector {
+            size: count,
+            max: max_count,
+        }));
+    }
+
+    Ok(count)
+}
+
+// Read a list of at most `max_count` elements. Peers choose the count, so the vector grows with
+// the elements actually decoded instead of being allocated upfront
+pub(crate) fn decode_vec<T: Decodable, R: Read + ?Sized>(
+    reader: &mut R,
+    max_count: u64,
+) -> Result<Vec<T>, DeserializeError> {
+    let count = read_count(reader, max_count)?;
+
+    let mut result: Vec<T> = Vec::new();
+    for i in 0..count {
+        result.push(T::decode(reader).map_err(|e| e.at_index(i))?);
+    }
+
+    Ok(result)
+}
+
+pub(crate) fn encode_slice<T: Encodable, W: Write + ?Sized>(
+    values: &[T],
+    writer: &mut W,
+) -> io::Result<usize> {
+    let mut size = CompactSize(values.len() as u64).encode(writer)?;
+    for value in values {
+        size += value.encode(writer)?;
+    }
+    Ok(size)
+}
+
+// Read a varint prefixed byte vector of at most `max_size` bytes. The buffer grows with the bytes
+// actually read, a bogus size cannot make us allocate more than the input
+pub(crate) fn read_var_bytes<R: Read + ?Sized>(
+    reader: &mut R,
+    max_size: u64,
+) -> Result<Vec<u8>, DeserializeError> {
+    let size = read_count(reader, max_size)?;
+
+    let mut buf: Vec<u8> = Vec::new();
+    reader.take(size).read_to_end(&mut buf)?;
+    if (buf.len() as u64) < size {
+        return Err(DeserializeError::new(ErrorKind::UnexpectedEof));
+    }
+
+    Ok(buf)
+}
+
+pub(crate) fn encode_var_bytes<W: Write + ?Sized>(
+    bytes: &[u8],
+    writer: &mut W,
+) -> io::Result<usize> {
+    let size = CompactSize(bytes.len() as u64).encode(writer)?;
+    writer.write_all(bytes)?;
+    Ok(size + bytes.len())
+}
+
+macro_rules! impl_int {
+    ($($int:ty),*) => {
+        $(
+            impl Encodable for $int {
+                fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                    writer.write_all(&self.to_le_bytes())?;
+                    Ok(size_of::<$int>())
+                }
+            }
+
+            impl Decodable for $int {
+                fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+                    let mut buf = [0u8; size_of::<$int>()];
+                    reader.read_exact(&mut buf)?;
+                    Ok(<$int>::from_le_bytes(buf))
+                }
+            }
+        )*
+    };
+}
+
+impl_int!(u8, u16, u32, u64, i32, i64);
+
+impl Encodable for bool {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        (*self as u8).encode(writer)
+    }
+}
+
+impl Decodable for bool {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DeserializeError::new(ErrorKind::InvalidValue {
+                field: "bool",
+            })),
+        }
+    }
+}
+
+impl<const N: usize> Encodable for [u8; N] {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        writer.write_all(self)?;
+        Ok(N)
+    }
+}
+
+impl<const N: usize> Decodable for [u8; N] {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        let mut buf = [0u8; N];
+        reader.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// CompactSize prefixed list.
+impl<T: Encodable> Encodable for Vec<T> {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        encode_slice(self, writer)
+    }
+}
+
+impl<T: Decodable> Decodable for Vec<T> {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        decode_vec(reader, u64::MAX)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_primitives() {
+        assert_eq!(serialize(&0x01020304u32), [4, 3, 2, 1]);
+        assert_eq!(
+            serialize(&-2i64),
+            [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+        assert_eq!(serialize(&[7u8; 3]), [7, 7, 7]);
+        assert_eq!(serialize(&vec![1u16, 2u16]), [2, 1, 0, 2, 0]);
+
+        let mut writer: Vec<u8> = vec![];
+        assert_eq!(vec![[1u8; 32]; 3].encode(&mut writer).unwrap(), 97);
+        assert_eq!(writer.len(), 97);
+    }
+
+    #[test]
+    fn test_decode_primitives() {
+        assert_eq!(deserialize::<u32>(&[4, 3, 2, 1]).unwrap(), 0x01020304);
+        assert_eq!(
+            deserialize_with_size::<Vec<u16>>(&[2, 1, 0, 2, 0, 0xff]).unwrap(),
+            (vec![1, 2], 5)
+        );
+        assert!(deserialize::<bool>(&[2]).is_err());
+
+        // Only 2 of the 3 announced elements are there
+        let err = deserialize::<Vec<u16>>(&[3, 1, 0, 2, 0]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+        assert_eq!(err.offset, 5);
+    }
+
+    #[test]
+    fn test_compact_size() {
+        for (value, raw) in [
+            (0, vec![0]),
+            (0xfc, vec![0xfc]),
+            (0xfd, vec![0xfd, 0xfd, 0]),
+            (0xffff, vec![0xfd, 0xff, 0xff]),
+            (0x10000, vec![0xfe, 0, 0, 1, 0]),
+            (0xffff_ffff, vec![0xfe, 0xff, 0xff, 0xff, 0xff]),
+            (0x1_0000_0000, vec![0xff, 0, 0, 0, 0, 1, 0, 0, 0]),
+            (u64::MAX, vec![0xff; 9]),
+        ] {
+            assert_eq!(serialize(&CompactSize(value)), raw);
+            assert_eq!(CompactSize(value).size(), raw.len());
+            assert_eq!(
+                deserialize_with_size::<CompactSize>(&raw).unwrap(),
+                (CompactSize(value), raw.len() as u64)
+            );
+        }
+
+        for raw in [
+            &[0xfd, 0x05, 0x00][..],
+            &[0xfe, 0xff, 0xff, 0, 0],
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0],
+        ] {
+            let err = deserialize::<CompactSize>(raw).unwrap_err();
+            assert_eq!(err.kind, ErrorKind::NonCanonicalVarInt);
+            assert_eq!(err.offset, raw.len() as u64);
+        }
+
+        let err = deserialize::<CompactSize>(&[0xfe, 1, 2]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn test_decode_from_reader() {
+        // Values are read one after the other from the same stream, without copying buffers
+        let mut reader = Reader::new(&[1, 0, 0, 0, 2, 0xaa, 0xbb, 3, 0xaa][..]);
+        assert_eq!(reader.decode::<u32>().unwrap(), 1);
+        assert_eq!(read_var_bytes(&mut reader, 10).unwrap(), [0xaa, 0xbb]);
+        assert_eq!(reader.position(), 7);
+
+        let err = reader.decode::<Vec<u8>>().unwrap_err();
+        assert_eq!(err.kind, ErrorKind::UnexpectedEof);
+        assert_eq!(err.offset, 9);
+    }
+
+    #[test]
+    fn test_custom_message() {
+        use crate::inventory::{Inventory, InventoryType};
+
+        // A message made of existing wire types
+        #[derive(Debug, PartialEq)]
+        struct Announce {
+            height: i32,
+            inventory: Vec<Inventory>,
+        }
+
+        impl Encodable for Announce {
+            fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                Ok(self.height.encode(writer)? + self.inventory.encode(writer)?)
+            }
+        }
+
+        impl Decodable for Announce {
+            fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+                within("Announce", || {
+                    Ok(Self {
+                        height: Decodable::decode(reader)?,
+                        inventory: Decodable::decode(reader)?,
+                    })
+                })
+            }
+        }
+
+        let announce = Announce {
+            height: 50057,
+            inventory: vec![Inventory {
+                identifier: InventoryType::Block,
+                hash: [1; 32],
+            }],
+        };
+        let raw = serialize(&announce);
+        assert_eq!(raw.len(), 4 + 1 + 36);
+        assert_eq!(deserialize::<Announce>(&raw).unwrap(), announce);
+
+        let err = deserialize::<Announce>(&raw[..20]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected end of input at byte 20 in Announce > Inventory #0"
+        );
+    }
+}
@@ -1,25 +1,256 @@
+use crate::services::ServiceFlags;
 use std::error::Error;
 use std::fmt::Display;
 
-#[derive(Debug)]
-pub struct DeserializeError(pub String);
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorKind {
+    // input ended in the middle of a value
+    UnexpectedEof,
+    // checksum of the header does not match the payload
+    InvalidChecksum { expected: [u8; 4], actual: [u8; 4] },
+    // varint not encoded with the smallest possible size
+    NonCanonicalVarInt,
+    // announced length or count over the protocol maximum or the remaining input
+    OversizedVector { size: u64, max: u64 },
+    InvalidUtf8,
+    InvalidValue { field: &'static str },
+    // bytes left after a message that has to fill its payload
+    TrailingBytes(u64),
+    // error of the underlying reader other than the end of input
+    Io(std::io::ErrorKind),
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ErrorKind::UnexpectedEof => write!(f, "Unexpected end of input"),
+            ErrorKind::InvalidChecksum { expected, actual } => write!(
+                f,
+                "Invalid checksum: header says {expected:02x?} but payload hashes to {actual:02x?}"
+            ),
+            ErrorKind::NonCanonicalVarInt => write!(f, "Non canonical varint"),
+            ErrorKind::OversizedVector { size, max } => {
+                write!(f, "Oversized vector: {size} elements, maximum is {max}")
+            }
+            ErrorKind::InvalidUtf8 => write!(f, "Invalid utf8 string"),
+            ErrorKind::InvalidValue { field } => write!(f, "Invalid value for {field}"),
+            ErrorKind::TrailingBytes(size) => write!(f, "{size} trailing bytes"),
+            ErrorKind::Io(kind) => write!(f, "I/O error: {kind}"),
+        }
+    }
+}
+
+/// Type being decoded when an error happened, with its index when it is part of a list.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub type_name: &'static str,
+    pub index: Option<u64>,
+}
+
+impl Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.index {
+            Some(index) => write!(f, "{} #{index}", self.type_name),
+            None => write!(f, "{}", self.type_name),
+        }
+    }
+}
+
+/// Error while decoding a wire type, e.g. "Unexpected end of input at byte 1234 in
+/// Block > Tx #37 > TxIn #2".
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeserializeError {
+    pub kind: ErrorKind,
+    // number of bytes read when the error was detected
+    pub offset: u64,
+    // outermost type first
+    pub context: Vec<Frame>,
+    // false until the offset is known, decoders reading from a stream do not know it
+    located: bool,
+}
+
+impl DeserializeError {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            offset: 0,
+            context: vec![],
+            located: false,
+        }
+    }
+
+    /// Set the offset if it is not known yet.
+    pub fn locate(mut self, offset: u64) -> Self {
+        if !self.located {
+            self.offset = offset;
+            self.located = true;
+        }
+        self
+    }
+
+    /// Add the type being decoded as the outermost frame.
+    pub fn within(mut self, type_name: &'static str) -> Self {
+        self.context.insert(
+            0,
+            Frame {
+                type_name,
+                index: None,
+            },
+        );
+        self
+    }
+
+    /// Set the index of the outermost type, when it failed to decode as part of a list.
+    pub fn at_index(mut self, index: u64) -> Self {
+        if let Some(frame) = self.context.first_mut() {
+            frame.index = Some(index);
+        }
+        self
+    }
+}
 
 impl Display for DeserializeError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{} at byte {}", self.kind, self.offset)?;
+        if !self.context.is_empty() {
+            let context: Vec<String> = self.context.iter().map(|frame| frame.to_string()).collect();
+            write!(f, " in {}", context.join(" > "))?;
+        }
+        Ok(())
     }
 }
 
 impl Error for DeserializeError {}
 
 impl From<std::io::Error> for DeserializeError {
-    fn from(_e: std::io::Error) -> Self {
-        DeserializeError("Failed to read varint".to_owned())
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            std::io::ErrorKind::UnexpectedEof => DeserializeError::new(ErrorKind::UnexpectedEof),
+            kind => DeserializeError::new(ErrorKind::Io(kind)),
+        }
     }
 }
 
 impl From<std::string::FromUtf8Error> for DeserializeError {
     fn from(_e: std::string::FromUtf8Error) -> Self {
-        DeserializeError("Failed to convert from utf8".to_owned())
+        DeserializeError::new(ErrorKind::InvalidUtf8)
+    }
+}
+
+#[derive(Debug)]
+pub struct ValidationError(pub String);
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for ValidationError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParseHashError {
+    // a hash is 64 hex characters
+    InvalidLength(usize),
+    InvalidCharacter,
+}
+
+impl Display for ParseHashError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseHashError::InvalidLength(length) => {
+                write!(
+                    f,
+                    "Invalid hash length: {} hex characters instead of 64",
+                    length
+                )
+            }
+            ParseHashError::InvalidCharacter => write!(f, "Invalid hex character in hash"),
+        }
+    }
+}
+
+impl Error for ParseHashError {}
+
+#[derive(Debug)]
+pub enum MessageError {
+    UnknownMagic([u8; 4]),
+    OversizedPayload(u32),
+    SizeMismatch { expected: u32, actual: usize },
+    // payload decoding errors, including a bad checksum (`ErrorKind::InvalidChecksum`)
+    Deserialize(DeserializeError),
+}
+
+impl Display for MessageError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MessageError::UnknownMagic(magic) => write!(f, "Unknown magic bytes {magic:02x?}"),
+            MessageError::OversizedPayload(size) => {
+                write!(f, "Payload size {size} exceeds the protocol limit")
+            }
+            MessageError::SizeMismatch { expected, actual } => write!(
+                f,
+                "Payload size mismatch: header says {expected} bytes but got {actual}"
+            ),
+            MessageError::Deserialize(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for MessageError {}
+
+impl From<DeserializeError> for MessageError {
+    fn from(e: DeserializeError) -> Self {
+        MessageError::Deserialize(e)
+    }
+}
+
+impl From<std::io::Error> for MessageError {
+    fn from(e: std::io::Error) -> Self {
+        MessageError::Deserialize(e.into())
+    }
+}
+
+#[derive(Debug)]
+pub enum HandshakeError {
+    // peer protocol version below the minimum we accept
+    ObsoleteVersion(i32),
+    // the peer sent back our own nonce, we are connected to ourselves
+    SelfConnection,
+    MissingServices(ServiceFlags),
+    // message not allowed at this stage of the handshake
+    UnexpectedMessage(String),
+    Message(MessageError),
+}
+
+impl Display for HandshakeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HandshakeError::ObsoleteVersion(version) => {
+                write!(f, "Peer protocol version {version} is too old")
+            }
+            HandshakeError::SelfConnection => write!(f, "Connected to ourselves"),
+            HandshakeError::MissingServices(services) => {
+                write!(f, "Peer does not offer the required services {services}")
+            }
+            HandshakeError::UnexpectedMessage(command) => {
+                write!(f, "Unexpected {command} message during the handshake")
+            }
+            HandshakeError::Message(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for HandshakeError {}
+
+impl From<MessageError> for HandshakeError {
+    fn from(e: MessageError) -> Self {
+        HandshakeError::Message(e)
+    }
+}
+
+impl From<DeserializeError> for HandshakeError {
+    fn from(e: DeserializeError) -> Self {
+        HandshakeError::Message(e.into())
     }
 }
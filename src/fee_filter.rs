@@ -0,0 +1,53 @@
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use std::io::{self, Read, Write};
+
+/// BIP133 `feefilter`: the peer does not want tx inv below this fee rate.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FeeFilter {
+    // satoshis per kilobyte
+    pub fee_rate: i64,
+}
+
+impl FeeFilter {
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        encode::deserialize_exact(bytes, "FeeFilter")
+    }
+}
+
+impl Encodable for FeeFilter {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.fee_rate.encode(writer)
+    }
+}
+
+impl Decodable for FeeFilter {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("FeeFilter", || {
+            Ok(Self {
+                fee_rate: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_fee_filter_deserialize() {
+        assert_eq!(
+            FeeFilter::deserialize(&[0xe8, 0x03, 0, 0, 0, 0, 0, 0]).unwrap(),
+            FeeFilter { fee_rate: 1000 }
+        );
+        assert!(FeeFilter::deserialize(&[0xe8, 0x03]).is_err());
+        let err = FeeFilter::deserialize(&[0xe8, 0x03, 0, 0, 0, 0, 0, 0, 0]).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+}
@@ -1,63 +1,155 @@
-use varint::VarInt;
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use crate::hash::BlockHash;
+use std::io::{self, Read, Write};
 
+// Maximum number of hashes in a block locator (MAX_LOCATOR_SZ in Bitcoin Core)
+pub const MAX_LOCATOR_SIZE: u64 = 101;
+
+/// Block locator format shared by `getblocks` and `getheaders`: the peer finds the first hash it
+/// knows and answers with the blocks following it, up to `stop_hash` or its own limit.
 #[derive(Debug, Clone, PartialEq)]
-pub struct GetBlocks {
-    pub version: u32,
-    pub hash_count: u64,
-    pub block_header_hashes: Vec<[u8; 32]>,
-    pub stop_hash: Option<[u8; 32]>,
+pub struct BlockLocator {
+    pub version: i32,
+    pub locator_hashes: Vec<BlockHash>,
+    // all zeros on the wire when not set
+    pub stop_hash: Option<BlockHash>,
+}
+
+impl Encodable for BlockLocator {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.version.encode(writer)?;
+        size += self.locator_hashes.encode(writer)?;
+        size += self.stop_hash.unwrap_or_default().encode(writer)?;
+        Ok(size)
+    }
+}
+
+impl Decodable for BlockLocator {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("BlockLocator", || {
+            let version = i32::decode(reader)?;
+
+            let locator_hashes: Vec<BlockHash> = encode::decode_vec(reader, MAX_LOCATOR_SIZE)?;
+
+            let stop_hash = match BlockHash::decode(reader)? {
+                x if x == BlockHash::default() => None,
+                x => Some(x),
+            };
+
+            Ok(Self {
+                version,
+                locator_hashes,
+                stop_hash,
+            })
+        })
+    }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetBlocks(pub BlockLocator);
+
 impl GetBlocks {
     pub fn new(
-        version: u32,
-        block_header_hashes: Vec<[u8; 32]>,
-        stop_hash: Option<[u8; 32]>,
+        version: i32,
+        locator_hashes: Vec<BlockHash>,
+        stop_hash: Option<BlockHash>,
     ) -> GetBlocks {
-        Self {
+        Self(BlockLocator {
             version,
-            hash_count: block_header_hashes.len() as u64,
-            block_header_hashes,
+            locator_hashes,
             stop_hash,
-        }
+        })
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.extend(self.version.to_le_bytes());
-        result.extend(VarInt::encode(self.hash_count).unwrap());
-        for element in &self.block_header_hashes {
-            result.extend(element);
-        }
-        match self.stop_hash {
-            Some(x) => result.extend(x),
-            None => result.extend([0_u8; 32]),
-        }
-        result
-    }
-
-    pub fn deserialize(bytes: &[u8]) -> GetBlocks {
-        let hash_count = VarInt::decode(&bytes[4..13]).unwrap();
-        let varint_size = VarInt::get_size(hash_count).unwrap();
-        let bytes_block_header_hashes = &bytes[(varint_size) as usize..];
-        let mut block_header_hashes: Vec<[u8; 32]> = Vec::new();
-        for i in 0..hash_count {
-            block_header_hashes.push(
-                bytes_block_header_hashes[(i * 32) as usize..((i + 1) * 32) as usize]
-                    .try_into()
-                    .unwrap(),
-            );
-        }
-        let last_bytes: [u8; 32] = bytes[(bytes.len() - 32)..].try_into().unwrap();
-        let stop_hash: Option<[u8; 32]> = match last_bytes {
-            x if x == [0_u8; 32] => None,
-            _ => Some(last_bytes),
-        };
-        Self {
-            version: u32::from_le_bytes(bytes[0..4].try_into().unwrap()),
-            hash_count: hash_count,
-            block_header_hashes,
-            stop_hash,
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<GetBlocks, DeserializeError> {
+        encode::deserialize_exact(bytes, "GetBlocks")
+    }
+}
+
+impl Encodable for GetBlocks {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.encode(writer)
+    }
+}
+
+impl Decodable for GetBlocks {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("GetBlocks", || Ok(Self(BlockLocator::decode(reader)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_get_blocks_serialize() {
+        let get_blocks = GetBlocks::new(70015, vec![BlockHash([1; 32])], Some(BlockHash([2; 32])));
+
+        let mut expected = vec![0x7f, 0x11, 0x01, 0x00, 0x01];
+        expected.extend([1; 32]);
+        expected.extend([2; 32]);
+        assert_eq!(get_blocks.serialize(), expected);
+    }
+
+    #[test]
+    fn test_get_blocks_deserialize() {
+        let get_blocks = GetBlocks::new(
+            70015,
+            vec![BlockHash([1; 32]), BlockHash([2; 32]), BlockHash([3; 32])],
+            None,
+        );
+
+        assert_eq!(
+            GetBlocks::deserialize(&get_blocks.serialize()).unwrap(),
+            get_blocks
+        );
+
+        let get_blocks = GetBlocks::new(70015, vec![], Some(BlockHash([4; 32])));
+        assert_eq!(
+            GetBlocks::deserialize(&get_blocks.serialize()).unwrap(),
+            get_blocks
+        );
+    }
+
+    #[test]
+    fn test_get_blocks_deserialize_invalid() {
+        let raw = GetBlocks::new(
+            70015,
+            vec![BlockHash([1; 32]), BlockHash([2; 32])],
+            Some(BlockHash([3; 32])),
+        )
+        .serialize();
+
+        // Every truncation fails instead of panicking
+        for length in 0..raw.len() {
+            assert!(GetBlocks::deserialize(&raw[..length]).is_err());
         }
+
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        let err = GetBlocks::deserialize(&trailing).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+        assert_eq!(err.offset, raw.len() as u64);
+
+        let too_many = GetBlocks::new(70015, vec![BlockHash([1; 32]); 102], None).serialize();
+        let err = GetBlocks::deserialize(&too_many).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::OversizedVector {
+                size: 102,
+                max: 101
+            }
+        );
+        // Detected once the count is read
+        assert_eq!(err.offset, 5);
+        let max = GetBlocks::new(70015, vec![BlockHash([1; 32]); 101], None).serialize();
+        assert!(GetBlocks::deserialize(&max).is_ok());
     }
 }
@@ -1,66 +1,51 @@
+use crate::encode::{self, Decodable, Encodable};
 use crate::error::DeserializeError;
-use crate::inventory::Inventory;
-use std::io::{Cursor, Read};
-use varint::VarInt;
+use crate::inventory::{self, Inventory};
+use std::io::{self, Read, Write};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct GetData {
-    pub count: u64,
     pub inventory: Vec<Inventory>,
 }
 
 impl GetData {
     pub fn new(inventory: Vec<Inventory>) -> Self {
-        Self {
-            count: inventory.len() as u64,
-            inventory,
-        }
+        Self { inventory }
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.extend(VarInt::encode(self.count).unwrap());
-        for element in &self.inventory {
-            result.extend(element.serialize());
-        }
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<GetData, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
-
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+        encode::deserialize_exact(bytes, "GetData")
+    }
+}
 
-        let mut inventory: Vec<Inventory> = Vec::new();
-        for _i in 0..count {
-            let mut buf = [0u8; 36];
-            cur.read_exact(&mut buf)?;
-            let inv = Inventory::deserialize(&buf)?;
+impl Encodable for GetData {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        encode::encode_slice(&self.inventory, writer)
+    }
+}
 
-            inventory.push(inv);
-        }
-        Ok(Self::new(inventory))
+impl Decodable for GetData {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("GetData", || Ok(Self::new(inventory::decode_list(reader)?)))
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::hash::Txid;
 
     #[test]
     fn test_get_data_serialize() {
-        let mut hash =
-            hex::decode("5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b")
-                .unwrap();
-        hash.reverse();
+        let txid: Txid = "5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b"
+            .parse()
+            .unwrap();
         assert_eq!(
-            GetData::new(vec![Inventory {
-                identifier: 1,
-                hash: hash.try_into().unwrap(),
-            }])
-            .serialize(),
+            GetData::new(vec![Inventory::from(txid)]).serialize(),
             [
                 1, 1, 0, 0, 0, 107, 147, 179, 136, 98, 239, 45, 76, 101, 74, 214, 240, 253, 45,
                 190, 104, 79, 55, 249, 227, 231, 46, 84, 176, 124, 122, 172, 68, 191, 0, 244, 91,
@@ -70,20 +55,16 @@ mod tests {
 
     #[test]
     fn test_get_data_deserialize() {
-        let mut hash =
-            hex::decode("5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b")
-                .unwrap();
-        hash.reverse();
+        let txid: Txid = "5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b"
+            .parse()
+            .unwrap();
         assert_eq!(
             GetData::deserialize(&[
                 1, 1, 0, 0, 0, 107, 147, 179, 136, 98, 239, 45, 76, 101, 74, 214, 240, 253, 45,
                 190, 104, 79, 55, 249, 227, 231, 46, 84, 176, 124, 122, 172, 68, 191, 0, 244, 91,
             ])
             .unwrap(),
-            GetData::new(vec![Inventory {
-                identifier: 1,
-                hash: hash.try_into().unwrap(),
-            }])
+            GetData::new(vec![Inventory::from(txid)])
         )
     }
 }
@@ -0,0 +1,72 @@
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use crate::get_blocks::BlockLocator;
+use crate::hash::BlockHash;
+use std::io::{self, Read, Write};
+
+/// Same locator format as `getblocks` but the peer answers with a `headers` message.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GetHeaders(pub BlockLocator);
+
+impl GetHeaders {
+    pub fn new(
+        version: i32,
+        locator_hashes: Vec<BlockHash>,
+        stop_hash: Option<BlockHash>,
+    ) -> GetHeaders {
+        Self(BlockLocator {
+            version,
+            locator_hashes,
+            stop_hash,
+        })
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<GetHeaders, DeserializeError> {
+        encode::deserialize_exact(bytes, "GetHeaders")
+    }
+}
+
+impl Encodable for GetHeaders {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.encode(writer)
+    }
+}
+
+impl Decodable for GetHeaders {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("GetHeaders", || Ok(Self(BlockLocator::decode(reader)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_headers_round_trip() {
+        let get_headers =
+            GetHeaders::new(70015, vec![BlockHash([1; 32]), BlockHash([2; 32])], None);
+        let raw = get_headers.serialize();
+
+        assert_eq!(raw.len(), 4 + 1 + 3 * 32);
+        assert_eq!(GetHeaders::deserialize(&raw).unwrap(), get_headers);
+
+        let get_headers =
+            GetHeaders::new(70015, vec![BlockHash([1; 32])], Some(BlockHash([3; 32])));
+        assert_eq!(
+            GetHeaders::deserialize(&get_headers.serialize()).unwrap(),
+            get_headers
+        );
+    }
+
+    #[test]
+    fn test_get_headers_too_many_hashes() {
+        let get_headers = GetHeaders::new(70015, vec![BlockHash([1; 32]); 102], None);
+
+        assert!(GetHeaders::deserialize(&get_headers.serialize()).is_err());
+    }
+}
@@ -0,0 +1,465 @@
+use crate::address::Address;
+use crate::error::HandshakeError;
+use crate::message::{Message, NetworkMessage};
+use crate::network::ChainParams;
+use crate::services::ServiceFlags;
+use crate::version::Version;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Oldest protocol version we talk to (MIN_PEER_PROTO_VERSION in Bitcoin Core)
+pub const MIN_PEER_PROTO_VERSION: i32 = 31800;
+// Protocol version from which peers understand sendheaders (BIP130)
+pub const SENDHEADERS_VERSION: i32 = 70012;
+// Protocol version from which peers understand wtxidrelay (BIP339) and sendaddrv2 (BIP155)
+pub const WTXID_RELAY_VERSION: i32 = 70016;
+
+/// Settings of the node, shared by all its connections. The nonce is what detects connections to
+/// ourselves, so every handshake of the node must use the same one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandshakeConfig {
+    // services we advertise
+    pub services: ServiceFlags,
+    // services outbound peers must advertise, otherwise the handshake fails
+    pub required_services: ServiceFlags,
+    pub min_version: i32,
+    pub user_agent: String,
+    pub start_height: i32,
+    pub relay: bool,
+    // nonce sent in our version messages, a peer version with the same nonce is our own
+    pub nonce: u64,
+}
+
+impl HandshakeConfig {
+    /// Default settings with the node `nonce`, e.g. drawn at random once at startup.
+    pub fn new(nonce: u64) -> Self {
+        Self {
+            services: ServiceFlags::NONE,
+            required_services: ServiceFlags::NETWORK,
+            min_version: MIN_PEER_PROTO_VERSION,
+            user_agent: "/bitcoin-network/".to_owned(),
+            start_height: 0,
+            relay: false,
+            nonce,
+        }
+    }
+}
+
+/// Which side opened the connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    // we connected to the peer
+    Outbound,
+    // the peer connected to us
+    Inbound,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HandshakeState {
+    AwaitingVersion,
+    AwaitingVerack,
+    Complete,
+}
+
+/// Features negotiated with the peer during the handshake.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Features {
+    // peer announces and requests transactions by wtxid (BIP339)
+    pub wtxid_relay: bool,
+    // peer wants addrv2 instead of addr (BIP155)
+    pub addr_v2: bool,
+    // peer wants new blocks announced with headers instead of inv (BIP130)
+    pub send_headers: bool,
+}
+
+/// Sans-IO version handshake with a single peer. Feed it the messages received from the peer
+/// and send the messages it returns, in order.
+#[derive(Debug, Clone)]
+pub struct Handshake {
+    params: ChainParams,
+    config: HandshakeConfig,
+    peer_address: Address,
+    direction: Direction,
+    state: HandshakeState,
+    version_sent: bool,
+    peer_version: Option<Version>,
+    features: Features,
+}
+
+impl Handshake {
+    pub fn new(
+        params: &ChainParams,
+        config: HandshakeConfig,
+        peer_address: Address,
+        direction: Direction,
+    ) -> Self {
+        Self {
+            params: params.clone(),
+            config,
+            peer_address,
+            direction,
+            state: HandshakeState::AwaitingVersion,
+            version_sent: false,
+            peer_version: None,
+            features: Features::default(),
+        }
+    }
+
+    /// Messages to send when we open the connection (outbound). For inbound connections wait
+    /// for the peer version instead, ours is sent in reply.
+    pub fn start(&mut self) -> Vec<Message> {
+        let version = self.send_version();
+        self.to_messages(vec![version])
+    }
+
+    /// Handle a message received from the peer and return the messages to send back.
+    pub fn receive(&mut self, message: &Message) -> Result<Vec<Message>, HandshakeError> {
+        let message = message.to_network_message(&self.params)?;
+        let replies = self.receive_network_message(message)?;
+
+        Ok(self.to_messages(replies))
+    }
+
+    pub fn receive_network_message(
+        &mut self,
+        message: NetworkMessage,
+    ) -> Result<Vec<NetworkMessage>, HandshakeError> {
+        let mut replies: Vec<NetworkMessage> = vec![];
+
+        match (self.state, message) {
+            (HandshakeState::AwaitingVersion, NetworkMessage::Version(version)) => {
+                self.check_version(&version)?;
+                self.peer_version = Some(version);
+
+                if !self.version_sent {
+                    replies.push(self.send_version());
+                }
+                // Both have to be sent before verack
+                if self.version().unwrap() >= WTXID_RELAY_VERSION {
+                    replies.push(NetworkMessage::WtxidRelay);
+                    replies.push(NetworkMessage::SendAddrV2);
+                }
+                replies.push(NetworkMessage::Verack);
+                self.state = HandshakeState::AwaitingVerack;
+            }
+            (HandshakeState::AwaitingVerack, NetworkMessage::Verack) => {
+                if self.version().unwrap() >= SENDHEADERS_VERSION {
+                    replies.push(NetworkMessage::SendHeaders);
+                }
+                self.state = HandshakeState::Complete;
+            }
+            (HandshakeState::AwaitingVerack, NetworkMessage::WtxidRelay) => {
+                // Bitcoin Core ignores it from peers that are too old to know about it
+                self.features.wtxid_relay |= self.version().unwrap() >= WTXID_RELAY_VERSION;
+            }
+            (HandshakeState::AwaitingVerack, NetworkMessage::SendAddrV2) => {
+                self.features.addr_v2 = true;
+            }
+            (
+                HandshakeState::AwaitingVerack | HandshakeState::Complete,
+                NetworkMessage::SendHeaders,
+            ) => {
+                self.features.send_headers = true;
+            }
+            (
+                _,
+                message @ (NetworkMessage::Version(_)
+                | NetworkMessage::Verack
+                | NetworkMessage::WtxidRelay
+                | NetworkMessage::SendAddrV2),
+            ) => {
+                return Err(HandshakeError::UnexpectedMessage(
+                    message.command().to_owned(),
+                ))
+            }
+            (HandshakeState::AwaitingVersion, message) => {
+                return Err(HandshakeError::UnexpectedMessage(
+                    message.command().to_owned(),
+                ))
+            }
+            // Not part of the handshake, let the caller handle it
+            _ => {}
+        }
+
+        Ok(replies)
+    }
+
+    pub fn state(&self) -> HandshakeState {
+        self.state
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.state == HandshakeState::Complete
+    }
+
+    /// Protocol version used with the peer: the lowest of ours and theirs.
+    pub fn version(&self) -> Option<i32> {
+        self.peer_version
+            .as_ref()
+            .map(|version| version.version.min(self.params.protocol_version))
+    }
+
+    pub fn peer_version(&self) -> Option<&Version> {
+        self.peer_version.as_ref()
+    }
+
+    pub fn features(&self) -> &Features {
+        &self.features
+    }
+
+    fn check_version(&self, version: &Version) -> Result<(), HandshakeError> {
+        if version.version < self.config.min_version {
+            return Err(HandshakeError::ObsoleteVersion(version.version));
+        }
+        if version.nonce != 0 && version.nonce == self.config.nonce {
+            return Err(HandshakeError::SelfConnection);
+        }
+        // Like Bitcoin Core, services are only required from the peers we chose, inbound peers
+        // such as SPV clients serve nothing
+        if self.direction == Direction::Outbound
+            && !version.services.has(self.config.required_services)
+        {
+            return Err(HandshakeError::MissingServices(ServiceFlags(
+                self.config.required_services.0 & !version.services.0,
+            )));
+        }
+
+        Ok(())
+    }
+
+    fn send_version(&mut self) -> NetworkMessage {
+        self.version_sent = true;
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        NetworkMessage::Version(Version {
+            version: self.params.protocol_version,
+            services: self.config.services,
+            timestamp,
+            addr_recv: self.peer_address.clone(),
+            addr_trans: Address {
+                services: self.config.services,
+                ip: [0; 16],
+                port: 0,
+            },
+            nonce: self.config.nonce,
+            user_agent: self.config.user_agent.clone(),
+            start_height: self.config.start_height,
+            relay: self.config.relay,
+        })
+    }
+
+    fn to_messages(&self, messages: Vec<NetworkMessage>) -> Vec<Message> {
+        messages
+            .into_iter()
+            .map(|message| message.into_message(&self.params))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::Network;
+
+    fn peer_version(version: i32, services: ServiceFlags, nonce: u64) -> Vec<u8> {
+        NetworkMessage::Version(Version {
+            version,
+            services,
+            timestamp: 1667596120,
+            addr_recv: Address::from("127.0.0.1:8333".parse::<std::net::SocketAddr>().unwrap()),
+            addr_trans: Address::from("127.0.0.1:8333".parse::<std::net::SocketAddr>().unwrap()),
+            nonce,
+            user_agent: "/Satoshi:25.0.0/".to_owned(),
+            start_height: 800000,
+            relay: true,
+        })
+        .into_message(Network::Bitcoin.params())
+        .serialize()
+    }
+
+    fn message(command: &str) -> Vec<u8> {
+        Message::new(Network::Bitcoin.params(), command.to_owned(), vec![]).serialize()
+    }
+
+    // Feed raw bytes received from the peer and return the raw bytes to send back
+    fn receive(handshake: &mut Handshake, bytes: &[u8]) -> Result<Vec<Vec<u8>>, HandshakeError> {
+        let message = Message::deserialize(bytes)?;
+        let replies = handshake.receive(&message)?;
+
+        Ok(replies.iter().map(|message| message.serialize()).collect())
+    }
+
+    fn commands(messages: &[Vec<u8>]) -> Vec<String> {
+        messages
+            .iter()
+            .map(|bytes| Message::deserialize(bytes).unwrap().command)
+            .collect()
+    }
+
+    fn config(nonce: u64) -> HandshakeConfig {
+        HandshakeConfig {
+            services: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+            required_services: ServiceFlags::NETWORK | ServiceFlags::WITNESS,
+            ..HandshakeConfig::new(nonce)
+        }
+    }
+
+    fn new_handshake(direction: Direction) -> Handshake {
+        let config = HandshakeConfig {
+            services: ServiceFlags::WITNESS,
+            ..config(7)
+        };
+        let peer_address = Address::from("1.2.3.4:8333".parse::<std::net::SocketAddr>().unwrap());
+
+        Handshake::new(Network::Bitcoin.params(), config, peer_address, direction)
+    }
+
+    // Deliver the messages sent by one side to the other and return its replies
+    fn deliver(to: &mut Handshake, messages: Vec<Message>) -> Result<Vec<Message>, HandshakeError> {
+        let mut replies = vec![];
+        for message in messages {
+            replies.extend(to.receive(&message)?);
+        }
+        Ok(replies)
+    }
+
+    #[test]
+    fn test_handshake_outbound() {
+        let mut handshake = new_handshake(Direction::Outbound);
+
+        let sent = handshake.start();
+        assert_eq!(sent.len(), 1);
+        let params = Network::Bitcoin.params();
+        let NetworkMessage::Version(version) = sent[0].to_network_message(params).unwrap() else {
+            panic!("expected a version message");
+        };
+        assert_eq!(version.version, 70016);
+        assert_eq!(version.services, ServiceFlags::WITNESS);
+        assert_eq!(version.addr_recv.to_string(), "1.2.3.4:8333");
+
+        let services = ServiceFlags::NETWORK | ServiceFlags::WITNESS;
+        let sent = receive(&mut handshake, &peer_version(70016, services, 42)).unwrap();
+        assert_eq!(commands(&sent), ["wtxidrelay", "sendaddrv2", "verack"]);
+        assert_eq!(handshake.state(), HandshakeState::AwaitingVerack);
+
+        assert!(receive(&mut handshake, &message("wtxidrelay"))
+            .unwrap()
+            .is_empty());
+        assert!(receive(&mut handshake, &message("sendaddrv2"))
+            .unwrap()
+            .is_empty());
+        let sent = receive(&mut handshake, &message("verack")).unwrap();
+        assert_eq!(commands(&sent), ["sendheaders"]);
+        assert!(handshake.is_complete());
+
+        receive(&mut handshake, &message("sendheaders")).unwrap();
+        assert_eq!(handshake.version(), Some(70016));
+        assert_eq!(
+            handshake.features(),
+            &Features {
+                wtxid_relay: true,
+                addr_v2: true,
+                send_headers: true,
+            }
+        );
+
+        // Feature negotiation is over once verack is received
+        assert!(receive(&mut handshake, &message("sendaddrv2")).is_err());
+        assert!(receive(&mut handshake, &message("verack")).is_err());
+    }
+
+    #[test]
+    fn test_handshake_inbound_old_peer() {
+        let mut handshake = new_handshake(Direction::Inbound);
+
+        let services = ServiceFlags::NETWORK | ServiceFlags::WITNESS;
+        let sent = receive(&mut handshake, &peer_version(70012, services, 42)).unwrap();
+        assert_eq!(commands(&sent), ["version", "verack"]);
+
+        // Too old for wtxidrelay, ignored
+        receive(&mut handshake, &message("wtxidrelay")).unwrap();
+        let sent = receive(&mut handshake, &message("verack")).unwrap();
+        assert_eq!(commands(&sent), ["sendheaders"]);
+        assert_eq!(handshake.version(), Some(70012));
+        assert_eq!(handshake.features(), &Features::default());
+    }
+
+    #[test]
+    fn test_handshake_rejected() {
+        let services = ServiceFlags::NETWORK | ServiceFlags::WITNESS;
+
+        let mut handshake = new_handshake(Direction::Outbound);
+        assert!(matches!(
+            receive(&mut handshake, &peer_version(31799, services, 42)),
+            Err(HandshakeError::ObsoleteVersion(31799))
+        ));
+
+        let mut handshake = new_handshake(Direction::Outbound);
+        assert!(matches!(
+            receive(
+                &mut handshake,
+                &peer_version(70016, ServiceFlags::NETWORK, 42)
+            ),
+            Err(HandshakeError::MissingServices(ServiceFlags::WITNESS))
+        ));
+        // Inbound peers do not have to serve anything
+        let mut handshake = new_handshake(Direction::Inbound);
+        assert!(receive(&mut handshake, &peer_version(70016, ServiceFlags::NONE, 42)).is_ok());
+
+        let mut handshake = new_handshake(Direction::Outbound);
+        assert!(matches!(
+            receive(&mut handshake, &message("verack")),
+            Err(HandshakeError::UnexpectedMessage(_))
+        ));
+
+        // Our own version coming back
+        let mut handshake = new_handshake(Direction::Outbound);
+        let sent = handshake.start();
+        let NetworkMessage::Version(mut version) = sent[0]
+            .to_network_message(Network::Bitcoin.params())
+            .unwrap()
+        else {
+            panic!("expected a version message");
+        };
+        version.services = services;
+        let message = NetworkMessage::Version(version)
+            .into_message(Network::Bitcoin.params())
+            .serialize();
+        assert!(matches!(
+            receive(&mut handshake, &message),
+            Err(HandshakeError::SelfConnection)
+        ));
+    }
+
+    #[test]
+    fn test_handshake_two_nodes() {
+        // Parameters built by the caller, not a built-in network
+        let params = &ChainParams {
+            name: "fork",
+            magic_bytes: [1, 2, 3, 4],
+            ..Network::Bitcoin.params().clone()
+        };
+        let address = Address::from("1.2.3.4:8333".parse::<std::net::SocketAddr>().unwrap());
+
+        let mut outbound = Handshake::new(params, config(1), address.clone(), Direction::Outbound);
+        let mut inbound = Handshake::new(params, config(2), address.clone(), Direction::Inbound);
+        let mut to_inbound = outbound.start();
+        while !to_inbound.is_empty() {
+            let to_outbound = deliver(&mut inbound, to_inbound).unwrap();
+            to_inbound = deliver(&mut outbound, to_outbound).unwrap();
+        }
+        assert!(outbound.is_complete());
+        assert!(inbound.is_complete());
+
+        // Same node on both ends: separately built configs with the node nonce
+        let mut outbound = Handshake::new(params, config(3), address.clone(), Direction::Outbound);
+        let mut inbound = Handshake::new(params, config(3), address, Direction::Inbound);
+        assert!(matches!(
+            deliver(&mut inbound, outbound.start()),
+            Err(HandshakeError::SelfConnection)
+        ));
+    }
+}
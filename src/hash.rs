@@ -0,0 +1,166 @@
+use crate::encode::{Decodable, Encodable};
+use crate::error::{DeserializeError, ParseHashError};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::str::FromStr;
+
+// 32 bytes hash in internal byte order. Like Bitcoin Core, it is displayed and parsed byte
+// reversed, which is how explorers show txids and block hashes
+macro_rules! hash_newtype {
+    ($(#[$attr:meta])* $name:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(pub [u8; 32]);
+
+        impl $name {
+            pub fn as_bytes(&self) -> &[u8; 32] {
+                &self.0
+            }
+
+            /// Parse a hash in constants, panics (at compile time) on invalid hex.
+            pub const fn from_hex_const(hex: &str) -> Self {
+                match hash_from_hex(hex) {
+                    Ok(hash) => Self(hash),
+                    Err(_) => panic!("invalid hash hex"),
+                }
+            }
+        }
+
+        impl From<[u8; 32]> for $name {
+            fn from(bytes: [u8; 32]) -> Self {
+                Self(bytes)
+            }
+        }
+
+        impl From<$name> for [u8; 32] {
+            fn from(hash: $name) -> Self {
+                hash.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                for byte in self.0.iter().rev() {
+                    write!(f, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Display::fmt(self, f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = ParseHashError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(Self(hash_from_hex(s)?))
+            }
+        }
+
+        impl Encodable for $name {
+            fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+                self.0.encode(writer)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+                Ok(Self(Decodable::decode(reader)?))
+            }
+        }
+    };
+}
+
+hash_newtype!(
+    /// Hash of a transaction without its witness data.
+    Txid
+);
+hash_newtype!(
+    /// Hash of a transaction including its witness data (BIP141).
+    Wtxid
+);
+hash_newtype!(
+    /// Hash of a block header.
+    BlockHash
+);
+hash_newtype!(
+    /// Root of the merkle tree of the txids of a block.
+    MerkleRoot
+);
+hash_newtype!(
+    /// Root of the merkle tree of the wtxids of a block (BIP141).
+    WitnessMerkleRoot
+);
+hash_newtype!(
+    /// Hash of the witness merkle root and the witness reserved value, found in the coinbase
+    /// outputs (BIP141).
+    WitnessCommitment
+);
+
+// Const so the chain parameters can hold hashes written as hex
+const fn hash_from_hex(hex: &str) -> Result<[u8; 32], ParseHashError> {
+    const fn nibble(c: u8) -> Result<u8, ParseHashError> {
+        match c {
+            b'0'..=b'9' => Ok(c - b'0'),
+            b'a'..=b'f' => Ok(c - b'a' + 10),
+            b'A'..=b'F' => Ok(c - b'A' + 10),
+            _ => Err(ParseHashError::InvalidCharacter),
+        }
+    }
+
+    let hex = hex.as_bytes();
+    if hex.len() != 64 {
+        return Err(ParseHashError::InvalidLength(hex.len()));
+    }
+
+    let mut hash = [0u8; 32];
+    let mut i = 0;
+    while i < 32 {
+        match (nibble(hex[2 * i]), nibble(hex[2 * i + 1])) {
+            (Ok(high), Ok(low)) => hash[31 - i] = high << 4 | low,
+            _ => return Err(ParseHashError::InvalidCharacter),
+        }
+        i += 1;
+    }
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encode;
+
+    #[test]
+    fn test_hash_display() {
+        let hex = "5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b";
+        let txid: Txid = hex.parse().unwrap();
+
+        assert_eq!(txid.0[0], 0x6b);
+        assert_eq!(txid.0[31], 0x5b);
+        assert_eq!(txid.to_string(), hex);
+        assert_eq!(format!("{:?}", txid), hex);
+        assert_eq!(encode::serialize(&txid), txid.0);
+        assert_eq!(encode::deserialize::<Txid>(&txid.0).unwrap(), txid);
+    }
+
+    #[test]
+    fn test_hash_parse_invalid() {
+        assert_eq!(
+            "5bf4".parse::<BlockHash>(),
+            Err(ParseHashError::InvalidLength(4))
+        );
+        assert_eq!(
+            "x".repeat(64).parse::<BlockHash>(),
+            Err(ParseHashError::InvalidCharacter)
+        );
+        assert_eq!(
+            format!("+{}", "0".repeat(63)).parse::<BlockHash>(),
+            Err(ParseHashError::InvalidCharacter)
+        );
+    }
+}
@@ -0,0 +1,190 @@
+use crate::block::{AuxPoWHeader, BlockHeader};
+use crate::encode::{self, Decodable, DecodableWithParams, Encodable};
+use crate::error::{DeserializeError, ErrorKind};
+use crate::hash::BlockHash;
+use crate::network::ChainParams;
+use std::io::{self, Read, Write};
+
+// Maximum number of headers in one message (MAX_HEADERS_RESULTS in Bitcoin Core)
+pub const MAX_HEADERS: u64 = 2000;
+
+/// A header as sent in `headers`, with its AuxPoW header on merged mined chains.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HeaderEntry {
+    pub header: BlockHeader,
+    pub auxpow_header: Option<AuxPoWHeader>,
+}
+
+impl HeaderEntry {
+    pub fn hash(&self) -> BlockHash {
+        self.header.hash()
+    }
+}
+
+impl From<BlockHeader> for HeaderEntry {
+    fn from(header: BlockHeader) -> Self {
+        Self {
+            header,
+            auxpow_header: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Headers {
+    pub headers: Vec<HeaderEntry>,
+}
+
+impl Headers {
+    pub fn new(headers: Vec<HeaderEntry>) -> Self {
+        Self { headers }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8], params: &ChainParams) -> Result<Headers, DeserializeError> {
+        encode::deserialize_exact_with_params(bytes, params, "Headers")
+    }
+}
+
+impl Encodable for HeaderEntry {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.header.encode(writer)?;
+        if let Some(auxpow_header) = &self.auxpow_header {
+            size += auxpow_header.encode(writer)?;
+        }
+        // Headers are sent as blocks without transactions
+        size += 0u8.encode(writer)?;
+        Ok(size)
+    }
+}
+
+impl DecodableWithParams for HeaderEntry {
+    fn decode_with_params<R: Read + ?Sized>(
+        reader: &mut R,
+        params: &ChainParams,
+    ) -> Result<Self, DeserializeError> {
+        encode::within("HeaderEntry", || {
+            let header = BlockHeader::decode(reader)?;
+
+            let mut auxpow_header = None;
+            if params.auxpow.is_some() && header.has_auxpow() {
+                auxpow_header = Some(AuxPoWHeader::decode(reader)?);
+            }
+
+            if u8::decode(reader)? != 0 {
+                // Headers are sent as blocks without transactions
+                return Err(DeserializeError::new(ErrorKind::InvalidValue {
+                    field: "tx count",
+                }));
+            }
+
+            Ok(Self {
+                header,
+                auxpow_header,
+            })
+        })
+    }
+}
+
+impl Encodable for Headers {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        encode::encode_slice(&self.headers, writer)
+    }
+}
+
+impl DecodableWithParams for Headers {
+    fn decode_with_params<R: Read + ?Sized>(
+        reader: &mut R,
+        params: &ChainParams,
+    ) -> Result<Self, DeserializeError> {
+        encode::within("Headers", || {
+            let count = encode::read_count(reader, MAX_HEADERS)?;
+
+            let mut headers: Vec<HeaderEntry> = Vec::new();
+            for i in 0..count {
+                headers.push(
+                    HeaderEntry::decode_with_params(reader, params).map_err(|e| e.at_index(i))?,
+                );
+            }
+
+            Ok(Self { headers })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::Block;
+    use crate::network::Network;
+    use std::fs;
+
+    #[test]
+    fn test_headers_round_trip() {
+        let block = Block::deserialize(
+            &fs::read("./raw_50057.bin").unwrap(),
+            Network::Dogecoin.params(),
+        )
+        .unwrap();
+        let headers = Headers::new(vec![
+            block.header.clone().into(),
+            block.header.clone().into(),
+        ]);
+        let raw = headers.serialize();
+
+        assert_eq!(raw.len(), 1 + 2 * 81);
+        assert_eq!(
+            Headers::deserialize(&raw, Network::Dogecoin.params()).unwrap(),
+            headers
+        );
+
+        let mut non_zero_count = raw.clone();
+        non_zero_count[81] = 1;
+        assert!(Headers::deserialize(&non_zero_count, Network::Dogecoin.params()).is_err());
+
+        let mut trailing = raw.clone();
+        trailing.push(0);
+        let err = Headers::deserialize(&trailing, Network::Dogecoin.params()).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+
+    #[test]
+    fn test_headers_auxpow() {
+        let block = Block::deserialize(
+            &fs::read("./raw_50057.bin").unwrap(),
+            Network::Dogecoin.params(),
+        )
+        .unwrap();
+        let mut header = block.header.clone();
+        header.version |= 0x100;
+        let auxpow_header = AuxPoWHeader {
+            coinbase_tx: block.transactions[0].clone(),
+            parent_hash: BlockHash::default(),
+            coinbase_branch: vec![[1; 32]],
+            coinbase_index: 0,
+            blockchain_branch: vec![],
+            blockchain_index: 0,
+            parent_block: block.header.clone(),
+        };
+        let headers = Headers::new(vec![HeaderEntry {
+            header,
+            auxpow_header: Some(auxpow_header),
+        }]);
+        let raw = headers.serialize();
+
+        assert_eq!(
+            Headers::deserialize(&raw, Network::Dogecoin.params()).unwrap(),
+            headers
+        );
+        // Without AuxPoW the same bytes are a header followed by garbage
+        assert!(Headers::deserialize(&raw, Network::Bitcoin.params()).is_err());
+    }
+
+    #[test]
+    fn test_headers_too_many() {
+        assert!(Headers::deserialize(&[0xfd, 0xd1, 0x07], Network::Bitcoin.params()).is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use crate::inventory::{self, Inventory};
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inv {
+    pub inventory: Vec<Inventory>,
+}
+
+impl Inv {
+    pub fn new(inventory: Vec<Inventory>) -> Self {
+        Self { inventory }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<Inv, DeserializeError> {
+        encode::deserialize_exact(bytes, "Inv")
+    }
+}
+
+impl Encodable for Inv {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        encode::encode_slice(&self.inventory, writer)
+    }
+}
+
+impl Decodable for Inv {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Inv", || Ok(Self::new(inventory::decode_list(reader)?)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::hash::Txid;
+    use crate::inventory::InventoryType;
+
+    #[test]
+    fn test_inv_round_trip() {
+        let inv = Inv::new(vec![
+            Inventory {
+                identifier: InventoryType::WitnessBlock,
+                hash: [1; 32],
+            },
+            Inventory {
+                identifier: InventoryType::Unknown(7),
+                hash: [2; 32],
+            },
+        ]);
+        let raw = inv.serialize();
+
+        assert_eq!(&raw[0..5], [2, 2, 0, 0, 0x40]);
+        assert_eq!(Inv::deserialize(&raw).unwrap(), inv);
+    }
+
+    #[test]
+    fn test_inv_deserialize_invalid() {
+        // 50001 entries announced
+        assert!(Inv::deserialize(&[0xfd, 0x51, 0xc3]).is_err());
+
+        let mut raw = Inv::new(vec![Inventory::from(Txid([1; 32])); 2]).serialize();
+        let error = Inv::deserialize(&raw[..raw.len() - 1]).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Unexpected end of input at byte 72 in Inv > Inventory #1"
+        );
+
+        raw.push(0);
+        let error = Inv::deserialize(&raw).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::TrailingBytes(1));
+    }
+}
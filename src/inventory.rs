@@ -1,51 +1,167 @@
+use crate::encode::{self, Decodable, Encodable};
 use crate::error::DeserializeError;
-use std::io::{Cursor, Read};
+use crate::hash::{BlockHash, Txid, Wtxid};
+use std::io::{self, Read, Write};
+
+// Maximum number of entries in inv, getdata and notfound (MAX_INV_SZ in Bitcoin Core)
+pub const MAX_INV_SIZE: u64 = 50_000;
+// Set on the type to request the witness serialization (BIP144)
+const MSG_WITNESS_FLAG: u32 = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InventoryType {
+    Error,
+    Tx,
+    Block,
+    FilteredBlock,
+    CompactBlock,
+    // wtxid based transaction relay (BIP339)
+    WTx,
+    WitnessTx,
+    WitnessBlock,
+    WitnessFilteredBlock,
+    Unknown(u32),
+}
+
+impl From<u32> for InventoryType {
+    fn from(value: u32) -> Self {
+        match value {
+            0 => InventoryType::Error,
+            1 => InventoryType::Tx,
+            2 => InventoryType::Block,
+            3 => InventoryType::FilteredBlock,
+            4 => InventoryType::CompactBlock,
+            5 => InventoryType::WTx,
+            x if x == MSG_WITNESS_FLAG | 1 => InventoryType::WitnessTx,
+            x if x == MSG_WITNESS_FLAG | 2 => InventoryType::WitnessBlock,
+            x if x == MSG_WITNESS_FLAG | 3 => InventoryType::WitnessFilteredBlock,
+            x => InventoryType::Unknown(x),
+        }
+    }
+}
+
+impl From<InventoryType> for u32 {
+    fn from(value: InventoryType) -> Self {
+        match value {
+            InventoryType::Error => 0,
+            InventoryType::Tx => 1,
+            InventoryType::Block => 2,
+            InventoryType::FilteredBlock => 3,
+            InventoryType::CompactBlock => 4,
+            InventoryType::WTx => 5,
+            InventoryType::WitnessTx => MSG_WITNESS_FLAG | 1,
+            InventoryType::WitnessBlock => MSG_WITNESS_FLAG | 2,
+            InventoryType::WitnessFilteredBlock => MSG_WITNESS_FLAG | 3,
+            InventoryType::Unknown(x) => x,
+        }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Inventory {
-    pub identifier: u32,
+    pub identifier: InventoryType,
     pub hash: [u8; 32],
 }
 impl Inventory {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.extend_from_slice(&self.identifier.to_le_bytes());
-        result.extend_from_slice(&self.hash);
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Inventory, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize(bytes)
+    }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let identifier = u32::from_le_bytes(buf);
+    /// Hash of the announced or requested transaction, witness or not.
+    pub fn txid(&self) -> Option<Txid> {
+        match self.identifier {
+            InventoryType::Tx | InventoryType::WitnessTx => Some(Txid(self.hash)),
+            _ => None,
+        }
+    }
+
+    pub fn wtxid(&self) -> Option<Wtxid> {
+        match self.identifier {
+            InventoryType::WTx => Some(Wtxid(self.hash)),
+            _ => None,
+        }
+    }
+
+    /// Hash of the announced or requested block, whatever form of the block is asked for.
+    pub fn block_hash(&self) -> Option<BlockHash> {
+        match self.identifier {
+            InventoryType::Block
+            | InventoryType::FilteredBlock
+            | InventoryType::CompactBlock
+            | InventoryType::WitnessBlock
+            | InventoryType::WitnessFilteredBlock => Some(BlockHash(self.hash)),
+            _ => None,
+        }
+    }
+}
 
-        let mut buf = [0u8; 32];
-        cur.read_exact(&mut buf)?;
-        let hash = buf;
+impl From<Txid> for Inventory {
+    fn from(txid: Txid) -> Self {
+        Self {
+            identifier: InventoryType::Tx,
+            hash: txid.0,
+        }
+    }
+}
 
-        Ok(Self { identifier, hash })
+// wtxid based relay (BIP339)
+impl From<Wtxid> for Inventory {
+    fn from(wtxid: Wtxid) -> Self {
+        Self {
+            identifier: InventoryType::WTx,
+            hash: wtxid.0,
+        }
     }
 }
 
+impl From<BlockHash> for Inventory {
+    fn from(hash: BlockHash) -> Self {
+        Self {
+            identifier: InventoryType::Block,
+            hash: hash.0,
+        }
+    }
+}
+
+impl Encodable for Inventory {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        Ok(u32::from(self.identifier).encode(writer)? + self.hash.encode(writer)?)
+    }
+}
+
+impl Decodable for Inventory {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Inventory", || {
+            Ok(Self {
+                identifier: u32::decode(reader)?.into(),
+                hash: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+/// Decoding shared by inv, getdata and notfound: a varint count followed by the entries.
+pub(crate) fn decode_list<R: Read + ?Sized>(
+    reader: &mut R,
+) -> Result<Vec<Inventory>, DeserializeError> {
+    encode::decode_vec(reader, MAX_INV_SIZE)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_inventory_serialize() {
-        let mut hash =
-            hex::decode("5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b")
-                .unwrap();
-        hash.reverse();
-        let bytes_reversed_hash = hash.try_into().unwrap();
+        let txid: Txid = "5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b"
+            .parse()
+            .unwrap();
         assert_eq!(
-            Inventory {
-                identifier: 1,
-                hash: bytes_reversed_hash,
-            }
-            .serialize(),
+            Inventory::from(txid).serialize(),
             [
                 1, 0, 0, 0, 107, 147, 179, 136, 98, 239, 45, 76, 101, 74, 214, 240, 253, 45, 190,
                 104, 79, 55, 249, 227, 231, 46, 84, 176, 124, 122, 172, 68, 191, 0, 244, 91,
@@ -55,21 +171,49 @@ mod tests {
 
     #[test]
     fn test_inventory_deserialize() {
-        let mut hash =
-            hex::decode("5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b")
-                .unwrap();
-        hash.reverse();
-        let bytes_reversed_hash = hash.try_into().unwrap();
+        let txid: Txid = "5bf400bf44ac7a7cb0542ee7e3f9374f68be2dfdf0d64a654c2def6288b3936b"
+            .parse()
+            .unwrap();
         assert_eq!(
             Inventory::deserialize(&[
                 1, 0, 0, 0, 107, 147, 179, 136, 98, 239, 45, 76, 101, 74, 214, 240, 253, 45, 190,
                 104, 79, 55, 249, 227, 231, 46, 84, 176, 124, 122, 172, 68, 191, 0, 244, 91,
             ])
             .unwrap(),
-            Inventory {
-                identifier: 1,
-                hash: bytes_reversed_hash,
-            }
+            Inventory::from(txid)
         );
     }
+
+    #[test]
+    fn test_inventory_hashes() {
+        let txid = Txid([1; 32]);
+        let inventory = Inventory::from(txid);
+        assert_eq!(inventory.identifier, InventoryType::Tx);
+        assert_eq!(inventory.txid(), Some(txid));
+        assert_eq!(inventory.block_hash(), None);
+
+        let inventory = Inventory::from(Wtxid([2; 32]));
+        assert_eq!(inventory.identifier, InventoryType::WTx);
+        assert_eq!(inventory.wtxid(), Some(Wtxid([2; 32])));
+        assert_eq!(inventory.txid(), None);
+
+        let inventory = Inventory {
+            identifier: InventoryType::WitnessBlock,
+            hash: [3; 32],
+        };
+        assert_eq!(inventory.block_hash(), Some(BlockHash([3; 32])));
+        assert_eq!(
+            Inventory::from(BlockHash([3; 32])).identifier,
+            InventoryType::Block
+        );
+    }
+
+    #[test]
+    fn test_inventory_type() {
+        for value in [0, 1, 2, 3, 4, 5, 0x40000001, 0x40000002, 0x40000003, 42] {
+            assert_eq!(u32::from(InventoryType::from(value)), value);
+        }
+        assert_eq!(InventoryType::from(0x40000002), InventoryType::WitnessBlock);
+        assert_eq!(InventoryType::from(42), InventoryType::Unknown(42));
+    }
 }
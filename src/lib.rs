@@ -1,12 +1,28 @@
-#![feature(cursor_split)]
-
+pub mod addr;
+pub mod addr_v2;
 pub mod address;
 pub mod block;
+pub mod decoder;
+pub mod encode;
 pub mod error;
+pub mod fee_filter;
 pub mod get_blocks;
 pub mod get_data;
+pub mod get_headers;
+pub mod handshake;
+pub mod hash;
+pub mod headers;
+pub mod inv;
 pub mod inventory;
+pub mod locator;
+pub mod merkle;
 pub mod message;
+pub mod network;
+pub mod not_found;
+pub mod ping;
+pub mod pow;
+pub mod reject;
+pub mod services;
 pub mod tx;
 mod utils;
 pub mod version;
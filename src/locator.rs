@@ -0,0 +1,230 @@
+use crate::get_blocks::GetBlocks;
+use crate::get_headers::GetHeaders;
+use crate::hash::BlockHash;
+use crate::headers::MAX_HEADERS;
+use crate::inventory::Inventory;
+use std::ops::Range;
+
+// Maximum number of block inventories sent in answer to getblocks
+pub const MAX_BLOCKS_RESULTS: usize = 500;
+
+/// Build a block locator from the hashes of a chain, `chain[0]` being the genesis block and the
+/// last element the tip. Same spacing as Bitcoin Core: the most recent blocks one by one, then
+/// doubling the step back, always ending with the genesis block.
+pub fn build_locator(chain: &[BlockHash]) -> Vec<BlockHash> {
+    let mut locator: Vec<BlockHash> = vec![];
+    if chain.is_empty() {
+        return locator;
+    }
+
+    let mut index = chain.len() - 1;
+    let mut step = 1;
+    loop {
+        locator.push(chain[index]);
+        if index == 0 {
+            break;
+        }
+        index = index.saturating_sub(step);
+        if locator.len() > 10 {
+            step *= 2;
+        }
+    }
+
+    locator
+}
+
+/// Position in `chain` of the first locator hash we know, the genesis block if none is known.
+/// `height_of` looks up the height of a block hash (e.g. in a `HashMap` index of the chain),
+/// scanning the chain for each locator hash would let peers make us do a lot of work.
+/// Hashes that are not in `chain` at that height (blocks of another branch) are skipped.
+pub fn find_fork(
+    chain: &[BlockHash],
+    height_of: impl Fn(&BlockHash) -> Option<usize>,
+    locator: &[BlockHash],
+) -> usize {
+    locator
+        .iter()
+        .find_map(|hash| height_of(hash).filter(|&height| chain.get(height) == Some(hash)))
+        .unwrap_or(0)
+}
+
+/// Positions in `chain` to send to a peer given its locator: the blocks following the fork point,
+/// at most `limit` of them and up to `stop_hash`, included only if `include_stop` is set.
+pub fn locate(
+    chain: &[BlockHash],
+    height_of: impl Fn(&BlockHash) -> Option<usize>,
+    locator: &[BlockHash],
+    stop_hash: Option<BlockHash>,
+    include_stop: bool,
+    limit: usize,
+) -> Range<usize> {
+    let start = (find_fork(chain, height_of, locator) + 1).min(chain.len());
+    let mut end = (start + limit).min(chain.len());
+    if let Some(stop_hash) = stop_hash {
+        if let Some(position) = chain[start..end].iter().position(|x| *x == stop_hash) {
+            end = start + position + include_stop as usize;
+        }
+    }
+
+    start..end
+}
+
+/// Block inventories to answer a getblocks with (at most 500). Like Bitcoin Core, the stop block
+/// itself is not sent.
+pub fn locate_blocks(
+    chain: &[BlockHash],
+    height_of: impl Fn(&BlockHash) -> Option<usize>,
+    get_blocks: &GetBlocks,
+) -> Vec<Inventory> {
+    chain[locate(
+        chain,
+        height_of,
+        &get_blocks.0.locator_hashes,
+        get_blocks.0.stop_hash,
+        false,
+        MAX_BLOCKS_RESULTS,
+    )]
+    .iter()
+    .map(|hash| Inventory::from(*hash))
+    .collect()
+}
+
+/// Positions of the headers to answer a getheaders with (at most 2000), the stop header included.
+/// Like Bitcoin Core, an empty locator asks for the stop header only.
+pub fn locate_headers(
+    chain: &[BlockHash],
+    height_of: impl Fn(&BlockHash) -> Option<usize>,
+    get_headers: &GetHeaders,
+) -> Range<usize> {
+    if get_headers.0.locator_hashes.is_empty() {
+        let stop = get_headers.0.stop_hash.and_then(|stop_hash| {
+            height_of(&stop_hash).filter(|&height| chain.get(height) == Some(&stop_hash))
+        });
+        return stop.map_or(0..0, |height| height..height + 1);
+    }
+
+    locate(
+        chain,
+        height_of,
+        &get_headers.0.locator_hashes,
+        get_headers.0.stop_hash,
+        true,
+        MAX_HEADERS as usize,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn chain(length: u32) -> Vec<BlockHash> {
+        (0..length)
+            .map(|height| {
+                let mut hash = [0u8; 32];
+                hash[0..4].copy_from_slice(&height.to_le_bytes());
+                BlockHash(hash)
+            })
+            .collect()
+    }
+
+    fn index(chain: &[BlockHash]) -> HashMap<BlockHash, usize> {
+        chain
+            .iter()
+            .enumerate()
+            .map(|(height, hash)| (*hash, height))
+            .collect()
+    }
+
+    fn heights(chain: &[BlockHash], hashes: &[BlockHash]) -> Vec<usize> {
+        hashes
+            .iter()
+            .map(|hash| chain.iter().position(|x| x == hash).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_build_locator() {
+        let chain = chain(100);
+
+        assert_eq!(
+            heights(&chain, &build_locator(&chain)),
+            vec![99, 98, 97, 96, 95, 94, 93, 92, 91, 90, 89, 88, 86, 82, 74, 58, 26, 0]
+        );
+        assert_eq!(heights(&chain, &build_locator(&chain[..3])), vec![2, 1, 0]);
+        assert!(build_locator(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_locate() {
+        let chain = chain(3000);
+        let index = index(&chain);
+        let height_of = |hash: &BlockHash| index.get(hash).copied();
+        let mut fork = chain[..1000].to_vec();
+        fork.push(BlockHash([0xff; 32]));
+
+        // The peer is on a fork from block 999
+        let locator = build_locator(&fork);
+        assert_eq!(find_fork(&chain, height_of, &locator), 999);
+        assert_eq!(
+            locate(&chain, height_of, &locator, None, true, 2000),
+            1000..3000
+        );
+        assert_eq!(
+            locate(&chain, height_of, &locator, Some(chain[1200]), true, 2000),
+            1000..1201
+        );
+        assert_eq!(
+            locate(&chain, height_of, &locator, Some(chain[1200]), false, 2000),
+            1000..1200
+        );
+
+        // Hashes indexed but not in the given chain, e.g. on a stale branch
+        assert_eq!(
+            find_fork(&chain[..500], height_of, &[chain[800], chain[400]]),
+            400
+        );
+
+        // Unknown locator starts from genesis
+        assert_eq!(
+            locate(&chain, height_of, &[BlockHash([0xff; 32])], None, true, 500),
+            1..501
+        );
+        // Peer already at our tip
+        assert_eq!(
+            locate(&chain, height_of, &build_locator(&chain), None, true, 500),
+            3000..3000
+        );
+    }
+
+    #[test]
+    fn test_locate_blocks() {
+        let chain = chain(1000);
+        let index = index(&chain);
+        let height_of = |hash: &BlockHash| index.get(hash).copied();
+        let get_blocks = GetBlocks::new(70015, build_locator(&chain[..10]), None);
+
+        let inventory = locate_blocks(&chain, height_of, &get_blocks);
+
+        assert_eq!(inventory.len(), MAX_BLOCKS_RESULTS);
+        assert_eq!(inventory[0].block_hash(), Some(chain[10]));
+
+        let get_headers = GetHeaders::new(70015, build_locator(&chain[..10]), None);
+        assert_eq!(locate_headers(&chain, height_of, &get_headers), 10..1000);
+
+        // The stop block is only sent in headers
+        let get_blocks = GetBlocks::new(70015, vec![chain[5]], Some(chain[8]));
+        let inventory = locate_blocks(&chain, height_of, &get_blocks);
+        assert_eq!(inventory, vec![chain[6].into(), chain[7].into()]);
+        let get_headers = GetHeaders::new(70015, vec![chain[5]], Some(chain[8]));
+        assert_eq!(locate_headers(&chain, height_of, &get_headers), 6..9);
+
+        // Without locator only the stop header is sent
+        let get_headers = GetHeaders::new(70015, vec![], Some(chain[8]));
+        assert_eq!(locate_headers(&chain, height_of, &get_headers), 8..9);
+        let get_headers = GetHeaders::new(70015, vec![], Some(BlockHash([0xff; 32])));
+        assert!(locate_headers(&chain, height_of, &get_headers).is_empty());
+        let get_headers = GetHeaders::new(70015, vec![], None);
+        assert!(locate_headers(&chain, height_of, &get_headers).is_empty());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::utils::double_hash;
+
+/// Compute the merkle root of a list of hashes (txids or wtxids) in internal byte order.
+pub fn merkle_root(hashes: &[[u8; 32]]) -> [u8; 32] {
+    merkle_root_with_mutation(hashes).0
+}
+
+/// Compute the merkle root and whether the list is mutated. Because the last node of an odd level
+/// is duplicated, `[a, b, c]` and `[a, b, c, c]` have the same root (CVE-2012-2459). A mutated list
+/// has two identical sibling nodes and must be rejected even if the root matches.
+pub fn merkle_root_with_mutation(hashes: &[[u8; 32]]) -> ([u8; 32], bool) {
+    if hashes.is_empty() {
+        return ([0u8; 32], false);
+    }
+
+    let mut mutated = false;
+    let mut level = hashes.to_vec();
+    while level.len() > 1 {
+        mutated |= level
+            .chunks_exact(2)
+            .any(|siblings| siblings[0] == siblings[1]);
+
+        if level.len() % 2 == 1 {
+            level.push(*level.last().unwrap());
+        }
+
+        level = level
+            .chunks_exact(2)
+            .map(|siblings| hash_nodes(&siblings[0], &siblings[1]))
+            .collect();
+    }
+
+    (level[0], mutated)
+}
+
+/// Compute the root reached from `hash` following `branch`, `index` is the position of `hash` in the tree.
+pub fn branch_root(mut hash: [u8; 32], branch: &[[u8; 32]], mut index: u32) -> [u8; 32] {
+    for node in branch {
+        hash = if index & 1 == 1 {
+            hash_nodes(node, &hash)
+        } else {
+            hash_nodes(&hash, node)
+        };
+        index >>= 1;
+    }
+    hash
+}
+
+fn hash_nodes(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    let mut concat: Vec<u8> = Vec::with_capacity(64);
+    concat.extend(left);
+    concat.extend(right);
+    double_hash(&concat)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merkle_root() {
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+        let c = [3u8; 32];
+
+        assert_eq!(merkle_root(&[]), [0u8; 32]);
+        assert_eq!(merkle_root(&[a]), a);
+        assert_eq!(merkle_root(&[a, b]), hash_nodes(&a, &b));
+        assert_eq!(
+            merkle_root(&[a, b, c]),
+            hash_nodes(&hash_nodes(&a, &b), &hash_nodes(&c, &c))
+        );
+    }
+
+    #[test]
+    fn test_merkle_root_mutation() {
+        let a = [1u8; 32];
+        let b = [2u8; 32];
+        let c = [3u8; 32];
+
+        let (root, mutated) = merkle_root_with_mutation(&[a, b, c]);
+        assert!(!mutated);
+
+        let (mutated_root, mutated) = merkle_root_with_mutation(&[a, b, c, c]);
+        assert!(mutated);
+        assert_eq!(mutated_root, root);
+    }
+
+    #[test]
+    fn test_branch_root() {
+        let hashes = [[1u8; 32], [2u8; 32], [3u8; 32], [4u8; 32]];
+        let root = merkle_root(&hashes);
+
+        let branch = [hashes[3], hash_nodes(&hashes[0], &hashes[1])];
+        assert_eq!(branch_root(hashes[2], &branch, 2), root);
+    }
+}
@@ -1,6 +1,26 @@
-use crate::error::DeserializeError;
-use crate::utils::checksum;
-use std::io::{Cursor, Read};
+use crate::addr::Addr;
+use crate::addr_v2::AddrV2;
+use crate::block::Block;
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::{DeserializeError, ErrorKind, MessageError};
+use crate::fee_filter::FeeFilter;
+use crate::get_blocks::GetBlocks;
+use crate::get_data::GetData;
+use crate::get_headers::GetHeaders;
+use crate::headers::Headers;
+use crate::inv::Inv;
+use crate::network::{ChainParams, Network};
+use crate::not_found::NotFound;
+use crate::ping::{Ping, Pong};
+use crate::reject::Reject;
+use crate::tx::Tx;
+use crate::utils;
+use crate::version::Version;
+use std::io::{self, Read, Write};
+
+pub const HEADER_SIZE: usize = 24;
+// Maximum payload size accepted (MAX_SIZE in Bitcoin Core)
+pub const MAX_PAYLOAD_SIZE: u32 = 32 * 1024 * 1024;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Message {
@@ -12,70 +32,313 @@ pub struct Message {
 }
 
 impl Message {
-    pub fn new(magic_bytes: [u8; 4], command: String, payload: Vec<u8>) -> Self {
+    pub fn new(params: &ChainParams, command: String, payload: Vec<u8>) -> Self {
         Self {
-            magic_bytes,
+            magic_bytes: params.magic_bytes,
             command,
             size: payload.len() as u32,
-            checksum: checksum(&payload),
+            checksum: utils::checksum(&payload),
             payload,
         }
     }
 
+    pub fn header(&self) -> MessageHeader {
+        MessageHeader {
+            magic_bytes: self.magic_bytes,
+            command: self.command.clone(),
+            size: self.size,
+            checksum: self.checksum,
+        }
+    }
+
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.extend(self.magic_bytes);
-        let mut command = self.command.as_bytes().to_owned();
-        command.resize(12, 0);
-        result.extend(command);
-        result.extend(self.size.to_le_bytes());
-        result.extend(self.checksum);
-        result.extend(&self.payload);
-        result
+        encode::serialize(self)
     }
 
-    pub fn deserialize(bytes: &[u8]) -> Result<Message, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+    /// Deserialize a message, checking the command name, the payload size and the checksum.
+    /// `bytes` must contain exactly one message. The magic bytes must be the ones of a built-in
+    /// `Network`, messages of other chains are read with `deserialize_with_magic`.
+    pub fn deserialize(bytes: &[u8]) -> Result<Message, MessageError> {
+        Self::deserialize_checked(bytes, |magic_bytes| {
+            Network::from_magic(magic_bytes).is_some()
+        })
+    }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let magic_bytes = buf;
+    /// Same as `deserialize` but only accepts messages of the `params` network.
+    pub fn deserialize_with_magic(
+        bytes: &[u8],
+        params: &ChainParams,
+    ) -> Result<Message, MessageError> {
+        Self::deserialize_checked(bytes, |magic_bytes| magic_bytes == params.magic_bytes)
+    }
+
+    fn deserialize_checked(
+        bytes: &[u8],
+        known_magic: impl FnOnce([u8; 4]) -> bool,
+    ) -> Result<Message, MessageError> {
+        let (header, payload) = bytes.split_at(bytes.len().min(HEADER_SIZE));
+        let header = MessageHeader::deserialize(header)?;
+        if !known_magic(header.magic_bytes) {
+            return Err(MessageError::UnknownMagic(header.magic_bytes));
+        }
+
+        header.with_payload(payload.to_vec())
+    }
 
-        let mut buf = [0u8; 12];
-        cur.read_exact(&mut buf)?;
-        let mut command = buf.to_vec();
-        command.retain(|&x| x != 0);
-        let command = String::from_utf8(command)?;
+    /// Decode the payload according to the command. Commands we do not support are kept as
+    /// `NetworkMessage::Unknown` so they can be ignored instead of breaking the connection.
+    /// `params` is needed to parse blocks of merged mined chains.
+    pub fn to_network_message(
+        &self,
+        params: &ChainParams,
+    ) -> Result<NetworkMessage, DeserializeError> {
+        let payload = &self.payload;
+        let message = match self.command.as_str() {
+            "version" => NetworkMessage::Version(Version::deserialize(payload)?),
+            "verack" => empty_payload(payload, "Verack", NetworkMessage::Verack)?,
+            "ping" => NetworkMessage::Ping(Ping::deserialize(payload)?),
+            "pong" => NetworkMessage::Pong(Pong::deserialize(payload)?),
+            "inv" => NetworkMessage::Inv(Inv::deserialize(payload)?),
+            "getdata" => NetworkMessage::GetData(GetData::deserialize(payload)?),
+            "notfound" => NetworkMessage::NotFound(NotFound::deserialize(payload)?),
+            "getblocks" => NetworkMessage::GetBlocks(GetBlocks::deserialize(payload)?),
+            "getheaders" => NetworkMessage::GetHeaders(GetHeaders::deserialize(payload)?),
+            "headers" => NetworkMessage::Headers(Headers::deserialize(payload, params)?),
+            "block" => NetworkMessage::Block(Block::deserialize(payload, params)?),
+            "tx" => NetworkMessage::Tx(Tx::deserialize(payload)?),
+            "addr" => NetworkMessage::Addr(Addr::deserialize(payload)?),
+            "addrv2" => NetworkMessage::AddrV2(AddrV2::deserialize(payload)?),
+            "getaddr" => empty_payload(payload, "GetAddr", NetworkMessage::GetAddr)?,
+            "sendaddrv2" => empty_payload(payload, "SendAddrV2", NetworkMessage::SendAddrV2)?,
+            "reject" => NetworkMessage::Reject(Reject::deserialize(payload)?),
+            "sendheaders" => empty_payload(payload, "SendHeaders", NetworkMessage::SendHeaders)?,
+            "wtxidrelay" => empty_payload(payload, "WtxidRelay", NetworkMessage::WtxidRelay)?,
+            "feefilter" => NetworkMessage::FeeFilter(FeeFilter::deserialize(payload)?),
+            _ => NetworkMessage::Unknown {
+                command: self.command.clone(),
+                payload: payload.clone(),
+            },
+        };
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let size = u32::from_le_bytes(buf);
+        Ok(message)
+    }
+}
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let checksum = buf;
+/// The 24 bytes preceding the payload, available before the payload is fully received.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageHeader {
+    pub magic_bytes: [u8; 4],
+    pub command: String,
+    pub size: u32,
+    pub checksum: [u8; 4],
+}
 
-        let payload = cur.split().1.to_vec();
+impl MessageHeader {
+    /// Deserialize and validate a header, the payload size is bounded by `MAX_PAYLOAD_SIZE`.
+    pub fn deserialize(bytes: &[u8]) -> Result<MessageHeader, MessageError> {
+        let header: MessageHeader = encode::deserialize_exact(bytes, "MessageHeader")?;
+        if header.size > MAX_PAYLOAD_SIZE {
+            return Err(MessageError::OversizedPayload(header.size));
+        }
 
-        // TODO: verify if checksum equal checksum(payload)
+        Ok(header)
+    }
 
-        Ok(Self {
-            magic_bytes,
-            command,
-            size,
-            checksum,
+    /// Build the message once the payload is received, checking its size and checksum.
+    pub fn with_payload(self, payload: Vec<u8>) -> Result<Message, MessageError> {
+        if payload.len() != self.size as usize {
+            return Err(MessageError::SizeMismatch {
+                expected: self.size,
+                actual: payload.len(),
+            });
+        }
+
+        let actual = utils::checksum(&payload);
+        if actual != self.checksum {
+            let kind = ErrorKind::InvalidChecksum {
+                expected: self.checksum,
+                actual,
+            };
+            return Err(DeserializeError::new(kind)
+                .locate((HEADER_SIZE + payload.len()) as u64)
+                .within("Message")
+                .into());
+        }
+
+        Ok(Message {
+            magic_bytes: self.magic_bytes,
+            command: self.command,
+            size: self.size,
+            checksum: self.checksum,
             payload,
         })
     }
 }
 
+impl Encodable for MessageHeader {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut command = [0u8; 12];
+        let length = self.command.len().min(12);
+        command[..length].copy_from_slice(&self.command.as_bytes()[..length]);
+
+        let mut size = self.magic_bytes.encode(writer)?;
+        size += command.encode(writer)?;
+        size += self.size.encode(writer)?;
+        size += self.checksum.encode(writer)?;
+        Ok(size)
+    }
+}
+
+/// Only checks the command name, the payload size is checked by `MessageHeader::deserialize`.
+impl Decodable for MessageHeader {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("MessageHeader", || {
+            Ok(Self {
+                magic_bytes: Decodable::decode(reader)?,
+                command: decode_command(Decodable::decode(reader)?)?,
+                size: Decodable::decode(reader)?,
+                checksum: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+impl Encodable for Message {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let size = self.header().encode(writer)?;
+        writer.write_all(&self.payload)?;
+        Ok(size + self.payload.len())
+    }
+}
+
+// Commands without payload, any byte is trailing
+fn empty_payload(
+    payload: &[u8],
+    type_name: &'static str,
+    message: NetworkMessage,
+) -> Result<NetworkMessage, DeserializeError> {
+    if !payload.is_empty() {
+        return Err(
+            DeserializeError::new(ErrorKind::TrailingBytes(payload.len() as u64))
+                .locate(0)
+                .within(type_name),
+        );
+    }
+
+    Ok(message)
+}
+
+// The command is printable ASCII padded with NUL bytes
+fn decode_command(bytes: [u8; 12]) -> Result<String, DeserializeError> {
+    let length = bytes.iter().position(|&x| x == 0).unwrap_or(12);
+    let (command, padding) = bytes.split_at(length);
+
+    if command.is_empty()
+        || !command.iter().all(|x| x.is_ascii_graphic())
+        || padding.iter().any(|&x| x != 0)
+    {
+        return Err(DeserializeError::new(ErrorKind::InvalidValue {
+            field: "command",
+        }));
+    }
+
+    Ok(command.iter().map(|&x| x as char).collect())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NetworkMessage {
+    Version(Version),
+    Verack,
+    Ping(Ping),
+    Pong(Pong),
+    Inv(Inv),
+    GetData(GetData),
+    NotFound(NotFound),
+    GetBlocks(GetBlocks),
+    GetHeaders(GetHeaders),
+    Headers(Headers),
+    Block(Block),
+    Tx(Tx),
+    Addr(Addr),
+    AddrV2(AddrV2),
+    GetAddr,
+    SendAddrV2,
+    Reject(Reject),
+    SendHeaders,
+    WtxidRelay,
+    FeeFilter(FeeFilter),
+    Unknown { command: String, payload: Vec<u8> },
+}
+
+impl NetworkMessage {
+    pub fn command(&self) -> &str {
+        match self {
+            NetworkMessage::Version(_) => "version",
+            NetworkMessage::Verack => "verack",
+            NetworkMessage::Ping(_) => "ping",
+            NetworkMessage::Pong(_) => "pong",
+            NetworkMessage::Inv(_) => "inv",
+            NetworkMessage::GetData(_) => "getdata",
+            NetworkMessage::NotFound(_) => "notfound",
+            NetworkMessage::GetBlocks(_) => "getblocks",
+            NetworkMessage::GetHeaders(_) => "getheaders",
+            NetworkMessage::Headers(_) => "headers",
+            NetworkMessage::Block(_) => "block",
+            NetworkMessage::Tx(_) => "tx",
+            NetworkMessage::Addr(_) => "addr",
+            NetworkMessage::AddrV2(_) => "addrv2",
+            NetworkMessage::GetAddr => "getaddr",
+            NetworkMessage::SendAddrV2 => "sendaddrv2",
+            NetworkMessage::Reject(_) => "reject",
+            NetworkMessage::SendHeaders => "sendheaders",
+            NetworkMessage::WtxidRelay => "wtxidrelay",
+            NetworkMessage::FeeFilter(_) => "feefilter",
+            NetworkMessage::Unknown { command, .. } => command,
+        }
+    }
+
+    pub fn payload(&self) -> Vec<u8> {
+        match self {
+            NetworkMessage::Version(version) => version.serialize(),
+            NetworkMessage::Ping(ping) => ping.serialize(),
+            NetworkMessage::Pong(pong) => pong.serialize(),
+            NetworkMessage::Inv(inv) => inv.serialize(),
+            NetworkMessage::GetData(get_data) => get_data.serialize(),
+            NetworkMessage::NotFound(not_found) => not_found.serialize(),
+            NetworkMessage::GetBlocks(get_blocks) => get_blocks.serialize(),
+            NetworkMessage::GetHeaders(get_headers) => get_headers.serialize(),
+            NetworkMessage::Headers(headers) => headers.serialize(),
+            NetworkMessage::Block(block) => block.serialize(),
+            NetworkMessage::Tx(tx) => tx.serialize(),
+            NetworkMessage::Addr(addr) => addr.serialize(),
+            NetworkMessage::AddrV2(addr_v2) => addr_v2.serialize(),
+            NetworkMessage::Reject(reject) => reject.serialize(),
+            NetworkMessage::FeeFilter(fee_filter) => fee_filter.serialize(),
+            NetworkMessage::Verack
+            | NetworkMessage::GetAddr
+            | NetworkMessage::SendAddrV2
+            | NetworkMessage::SendHeaders
+            | NetworkMessage::WtxidRelay => vec![],
+            NetworkMessage::Unknown { payload, .. } => payload.clone(),
+        }
+    }
+
+    pub fn into_message(self, params: &ChainParams) -> Message {
+        Message::new(params, self.command().to_owned(), self.payload())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
     #[test]
     fn test_message_serialize() {
-        let verack = Message::new([0xFC, 0xC1, 0xB7, 0xDC], "verack".to_string(), vec![]);
+        let verack = Message::new(
+            Network::DogecoinTestnet.params(),
+            "verack".to_string(),
+            vec![],
+        );
         assert_eq!(
             verack.serialize(),
             [
@@ -93,7 +356,155 @@ mod tests {
         ];
         assert_eq!(
             Message::deserialize(&bytes).unwrap(),
-            Message::new([0xFC, 0xC1, 0xB7, 0xDC], "verack".to_string(), vec![])
+            Message::new(
+                Network::DogecoinTestnet.params(),
+                "verack".to_string(),
+                vec![]
+            )
+        );
+    }
+
+    #[test]
+    fn test_message_deserialize_invalid() {
+        let bytes = Message::new(
+            Network::DogecoinTestnet.params(),
+            "ping".to_string(),
+            vec![1; 8],
+        )
+        .serialize();
+        assert!(Message::deserialize(&bytes).is_ok());
+        assert!(matches!(
+            Message::deserialize_with_magic(&bytes, Network::Bitcoin.params()),
+            Err(MessageError::UnknownMagic(_))
+        ));
+
+        let mut unknown_magic = bytes.clone();
+        unknown_magic[..4].copy_from_slice(&[0; 4]);
+        assert!(matches!(
+            Message::deserialize(&unknown_magic),
+            Err(MessageError::UnknownMagic([0, 0, 0, 0]))
+        ));
+
+        let mut bad_checksum = bytes.clone();
+        bad_checksum[20] ^= 1;
+        assert!(matches!(
+            Message::deserialize(&bad_checksum),
+            Err(MessageError::Deserialize(DeserializeError {
+                kind: ErrorKind::InvalidChecksum { .. },
+                ..
+            }))
+        ));
+
+        let mut bad_payload = bytes.clone();
+        bad_payload[30] ^= 1;
+        assert!(matches!(
+            Message::deserialize(&bad_payload),
+            Err(MessageError::Deserialize(DeserializeError {
+                kind: ErrorKind::InvalidChecksum { .. },
+                ..
+            }))
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            Message::deserialize(&trailing),
+            Err(MessageError::SizeMismatch { .. })
+        ));
+        assert!(matches!(
+            Message::deserialize(&bytes[..bytes.len() - 1]),
+            Err(MessageError::SizeMismatch { .. })
+        ));
+
+        let mut oversized = bytes.clone();
+        oversized[16..20].copy_from_slice(&(MAX_PAYLOAD_SIZE + 1).to_le_bytes());
+        assert!(matches!(
+            Message::deserialize(&oversized),
+            Err(MessageError::OversizedPayload(_))
+        ));
+
+        // "ping\0x" is not NUL padded
+        let mut bad_padding = bytes.clone();
+        bad_padding[9] = b'x';
+        assert!(matches!(
+            Message::deserialize(&bad_padding),
+            Err(MessageError::Deserialize(DeserializeError {
+                kind: ErrorKind::InvalidValue { field: "command" },
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn test_to_network_message() {
+        let verack = Message::new(
+            Network::DogecoinTestnet.params(),
+            "verack".to_string(),
+            vec![],
+        );
+        assert_eq!(
+            verack
+                .to_network_message(Network::DogecoinTestnet.params())
+                .unwrap(),
+            NetworkMessage::Verack
+        );
+
+        let fee_filter = NetworkMessage::FeeFilter(FeeFilter { fee_rate: 1000 })
+            .into_message(Network::DogecoinTestnet.params());
+        assert_eq!(fee_filter.command, "feefilter");
+        assert_eq!(
+            Message::deserialize(&fee_filter.serialize())
+                .unwrap()
+                .to_network_message(Network::DogecoinTestnet.params())
+                .unwrap(),
+            NetworkMessage::FeeFilter(FeeFilter { fee_rate: 1000 })
+        );
+
+        let unknown = Message::new(
+            Network::DogecoinTestnet.params(),
+            "cmpctblock".to_string(),
+            vec![1, 2],
         );
+        let network_message = unknown
+            .to_network_message(Network::DogecoinTestnet.params())
+            .unwrap();
+        assert_eq!(
+            network_message,
+            NetworkMessage::Unknown {
+                command: "cmpctblock".to_owned(),
+                payload: vec![1, 2]
+            }
+        );
+        assert_eq!(
+            network_message.into_message(Network::DogecoinTestnet.params()),
+            unknown
+        );
+
+        let bad_fee_filter = Message::new(
+            Network::DogecoinTestnet.params(),
+            "feefilter".to_string(),
+            vec![1],
+        );
+        assert!(bad_fee_filter
+            .to_network_message(Network::DogecoinTestnet.params())
+            .is_err());
+
+        for command in [
+            "verack",
+            "getaddr",
+            "sendaddrv2",
+            "sendheaders",
+            "wtxidrelay",
+        ] {
+            let message = Message::new(
+                Network::DogecoinTestnet.params(),
+                command.to_string(),
+                vec![0; 2],
+            );
+            let err = message
+                .to_network_message(Network::DogecoinTestnet.params())
+                .unwrap_err();
+            assert_eq!(err.kind, ErrorKind::TrailingBytes(2));
+        }
     }
 }
@@ -0,0 +1,241 @@
+use crate::hash::BlockHash;
+
+/// Networks with built-in parameters. Other forks can build their own `ChainParams`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Network {
+    Bitcoin,
+    BitcoinTestnet,
+    BitcoinSignet,
+    BitcoinRegtest,
+    Dogecoin,
+    DogecoinTestnet,
+    Litecoin,
+    LitecoinTestnet,
+    Namecoin,
+}
+
+impl Network {
+    pub const ALL: [Network; 9] = [
+        Network::Bitcoin,
+        Network::BitcoinTestnet,
+        Network::BitcoinSignet,
+        Network::BitcoinRegtest,
+        Network::Dogecoin,
+        Network::DogecoinTestnet,
+        Network::Litecoin,
+        Network::LitecoinTestnet,
+        Network::Namecoin,
+    ];
+
+    pub fn params(&self) -> &'static ChainParams {
+        match self {
+            Network::Bitcoin => &BITCOIN,
+            Network::BitcoinTestnet => &BITCOIN_TESTNET,
+            Network::BitcoinSignet => &BITCOIN_SIGNET,
+            Network::BitcoinRegtest => &BITCOIN_REGTEST,
+            Network::Dogecoin => &DOGECOIN,
+            Network::DogecoinTestnet => &DOGECOIN_TESTNET,
+            Network::Litecoin => &LITECOIN,
+            Network::LitecoinTestnet => &LITECOIN_TESTNET,
+            Network::Namecoin => &NAMECOIN,
+        }
+    }
+
+    pub fn from_magic(magic_bytes: [u8; 4]) -> Option<Network> {
+        Self::ALL
+            .into_iter()
+            .find(|network| network.params().magic_bytes == magic_bytes)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainParams {
+    pub name: &'static str,
+    pub magic_bytes: [u8; 4],
+    pub default_port: u16,
+    // hash of the genesis block in internal byte order
+    pub genesis_hash: BlockHash,
+    // protocol version we advertise in our version message
+    pub protocol_version: i32,
+    // merged mining parameters, `None` for chains without AuxPoW
+    pub auxpow: Option<AuxPoWParams>,
+    pub pubkey_address_prefix: u8,
+    pub script_address_prefix: u8,
+    pub secret_key_prefix: u8,
+    pub bech32_hrp: Option<&'static str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuxPoWParams {
+    pub chain_id: u32,
+    // first height where blocks are allowed to carry an AuxPoW header
+    pub start_height: u32,
+}
+
+pub const BITCOIN: ChainParams = ChainParams {
+    name: "bitcoin",
+    magic_bytes: [0xF9, 0xBE, 0xB4, 0xD9],
+    default_port: 8333,
+    genesis_hash: BlockHash::from_hex_const(
+        "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 0,
+    script_address_prefix: 5,
+    secret_key_prefix: 128,
+    bech32_hrp: Some("bc"),
+};
+
+pub const BITCOIN_TESTNET: ChainParams = ChainParams {
+    name: "bitcoin-testnet",
+    magic_bytes: [0x0B, 0x11, 0x09, 0x07],
+    default_port: 18333,
+    genesis_hash: BlockHash::from_hex_const(
+        "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 111,
+    script_address_prefix: 196,
+    secret_key_prefix: 239,
+    bech32_hrp: Some("tb"),
+};
+
+pub const BITCOIN_SIGNET: ChainParams = ChainParams {
+    name: "bitcoin-signet",
+    magic_bytes: [0x0A, 0x03, 0xCF, 0x40],
+    default_port: 38333,
+    genesis_hash: BlockHash::from_hex_const(
+        "00000008819873e925422c1ff0f99f7cc9bbb232af63a077a480a3633bee1ef6",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 111,
+    script_address_prefix: 196,
+    secret_key_prefix: 239,
+    bech32_hrp: Some("tb"),
+};
+
+pub const BITCOIN_REGTEST: ChainParams = ChainParams {
+    name: "bitcoin-regtest",
+    magic_bytes: [0xFA, 0xBF, 0xB5, 0xDA],
+    default_port: 18444,
+    genesis_hash: BlockHash::from_hex_const(
+        "0f9188f13cb7b2c71f2a335e3a4fc328bf5beb436012afca590b1a11466e2206",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 111,
+    script_address_prefix: 196,
+    secret_key_prefix: 239,
+    bech32_hrp: Some("bcrt"),
+};
+
+pub const DOGECOIN: ChainParams = ChainParams {
+    name: "dogecoin",
+    magic_bytes: [0xC0, 0xC0, 0xC0, 0xC0],
+    default_port: 22556,
+    genesis_hash: BlockHash::from_hex_const(
+        "1a91e3dace36e2be3bf030a65679fe821aa1d6ef92e7c9902eb318182c355691",
+    ),
+    protocol_version: 70015,
+    auxpow: Some(AuxPoWParams {
+        chain_id: 0x0062,
+        start_height: 371337,
+    }),
+    pubkey_address_prefix: 30,
+    script_address_prefix: 22,
+    secret_key_prefix: 158,
+    bech32_hrp: None,
+};
+
+pub const DOGECOIN_TESTNET: ChainParams = ChainParams {
+    name: "dogecoin-testnet",
+    magic_bytes: [0xFC, 0xC1, 0xB7, 0xDC],
+    default_port: 44556,
+    genesis_hash: BlockHash::from_hex_const(
+        "bb0a78264637406b6360aad926284d544d7049f45189db5664f3c4d07350559e",
+    ),
+    protocol_version: 70015,
+    auxpow: Some(AuxPoWParams {
+        chain_id: 0x0062,
+        start_height: 158100,
+    }),
+    pubkey_address_prefix: 113,
+    script_address_prefix: 196,
+    secret_key_prefix: 241,
+    bech32_hrp: None,
+};
+
+pub const LITECOIN: ChainParams = ChainParams {
+    name: "litecoin",
+    magic_bytes: [0xFB, 0xC0, 0xB6, 0xDB],
+    default_port: 9333,
+    genesis_hash: BlockHash::from_hex_const(
+        "12a765e31ffd4059bada1e25190f6e98c99d9714d334efa41a195a7e7e04bfe2",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 48,
+    script_address_prefix: 50,
+    secret_key_prefix: 176,
+    bech32_hrp: Some("ltc"),
+};
+
+pub const LITECOIN_TESTNET: ChainParams = ChainParams {
+    name: "litecoin-testnet",
+    magic_bytes: [0xFD, 0xD2, 0xC8, 0xF1],
+    default_port: 19335,
+    genesis_hash: BlockHash::from_hex_const(
+        "4966625a4b2851d9fdee139e56211a0d88575f59ed816ff5e6a63deb4e3e29a0",
+    ),
+    protocol_version: 70016,
+    auxpow: None,
+    pubkey_address_prefix: 111,
+    script_address_prefix: 58,
+    secret_key_prefix: 239,
+    bech32_hrp: Some("tltc"),
+};
+
+pub const NAMECOIN: ChainParams = ChainParams {
+    name: "namecoin",
+    magic_bytes: [0xF9, 0xBE, 0xB4, 0xFE],
+    default_port: 8334,
+    genesis_hash: BlockHash::from_hex_const(
+        "000000000062b72c5e2ceb45fbc8587e807c155b0da735e6483dfba2f0a9c770",
+    ),
+    protocol_version: 70016,
+    auxpow: Some(AuxPoWParams {
+        chain_id: 0x0001,
+        start_height: 19200,
+    }),
+    pubkey_address_prefix: 52,
+    script_address_prefix: 13,
+    secret_key_prefix: 180,
+    bech32_hrp: Some("nc"),
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_magic() {
+        for network in Network::ALL {
+            assert_eq!(
+                Network::from_magic(network.params().magic_bytes),
+                Some(network)
+            );
+        }
+        assert_eq!(Network::from_magic([0, 0, 0, 0]), None);
+    }
+
+    #[test]
+    fn test_genesis_hash() {
+        assert_eq!(
+            Network::Bitcoin.params().genesis_hash.to_string(),
+            "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f"
+        );
+    }
+}
@@ -0,0 +1,75 @@
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use crate::inventory::{self, Inventory};
+use std::io::{self, Read, Write};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NotFound {
+    pub inventory: Vec<Inventory>,
+}
+
+impl NotFound {
+    pub fn new(inventory: Vec<Inventory>) -> Self {
+        Self { inventory }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<NotFound, DeserializeError> {
+        encode::deserialize_exact(bytes, "NotFound")
+    }
+}
+
+impl Encodable for NotFound {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        encode::encode_slice(&self.inventory, writer)
+    }
+}
+
+impl Decodable for NotFound {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("NotFound", || {
+            Ok(Self::new(inventory::decode_list(reader)?))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+    use crate::hash::{BlockHash, Txid};
+    use crate::inventory::MAX_INV_SIZE;
+
+    #[test]
+    fn test_not_found_round_trip() {
+        let not_found = NotFound::new(vec![
+            Inventory::from(Txid([1; 32])),
+            Inventory::from(BlockHash([2; 32])),
+        ]);
+        let raw = not_found.serialize();
+
+        assert_eq!(raw.len(), 1 + 2 * 36);
+        assert_eq!(NotFound::deserialize(&raw).unwrap(), not_found);
+    }
+
+    #[test]
+    fn test_not_found_deserialize_invalid() {
+        // 50001 entries announced
+        let err = NotFound::deserialize(&[0xfd, 0x51, 0xc3]).unwrap_err();
+        assert_eq!(
+            err.kind,
+            ErrorKind::OversizedVector {
+                size: MAX_INV_SIZE + 1,
+                max: MAX_INV_SIZE
+            }
+        );
+
+        let mut raw = NotFound::new(vec![Inventory::from(Txid([1; 32]))]).serialize();
+        raw.push(0);
+        let err = NotFound::deserialize(&raw).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+}
@@ -1,5 +1,13 @@
+use crate::encode::{self, Decodable, Encodable};
 use crate::error::DeserializeError;
-use std::io::{Cursor, Read};
+use crate::utils;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::io::{self, Read, Write};
+use std::time::{Duration, Instant};
+
+// Protocol versions above this one send a nonce in ping and answer with pong (BIP31)
+pub const BIP31_VERSION: i32 = 60000;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Ping {
@@ -7,24 +15,202 @@ pub struct Ping {
 }
 
 impl Ping {
+    pub fn random() -> Self {
+        Self {
+            nonce: utils::random_nonce(),
+        }
+    }
+
+    /// Whether a peer with this protocol version answers pings with a pong.
+    pub fn expects_pong(version: i32) -> bool {
+        version > BIP31_VERSION
+    }
+
+    pub fn pong(&self) -> Pong {
+        Pong { nonce: self.nonce }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    /// Peers before BIP31 expect an empty ping.
+    pub fn serialize_for_version(&self, version: i32) -> Vec<u8> {
+        if Self::expects_pong(version) {
+            self.serialize()
+        } else {
+            vec![]
+        }
+    }
+
+    /// An empty payload is a ping from a peer before BIP31, it is read with a zero nonce.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        if bytes.is_empty() {
+            return Ok(Self { nonce: 0 });
+        }
+
+        encode::deserialize_exact(bytes, "Ping")
+    }
+}
+
+impl Encodable for Ping {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.nonce.encode(writer)
+    }
+}
+
+impl Decodable for Ping {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Ping", || {
+            Ok(Self {
+                nonce: Decodable::decode(reader)?,
+            })
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pong {
+    pub nonce: u64,
+}
+
+impl Pong {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-        result.extend_from_slice(&self.nonce.to_le_bytes());
-        
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Self, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize_exact(bytes, "Pong")
+    }
+}
 
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let nonce = u64::from_le_bytes(buf);
+impl Encodable for Pong {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.nonce.encode(writer)
+    }
+}
 
-        Ok(Self {
-            nonce,
+impl Decodable for Pong {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Pong", || {
+            Ok(Self {
+                nonce: Decodable::decode(reader)?,
+            })
         })
     }
 }
 
-pub type Pong = Ping;
+/// Keep track of the outstanding ping of each peer and measure the round trip when the pong arrives.
+/// Time is given by the caller so it can be driven by any event loop (and by tests).
+#[derive(Debug, Clone)]
+pub struct PingTracker<P> {
+    outstanding: HashMap<P, (u64, Instant)>,
+    latencies: HashMap<P, Duration>,
+}
+
+impl<P: Eq + Hash> Default for PingTracker<P> {
+    fn default() -> Self {
+        Self {
+            outstanding: HashMap::new(),
+            latencies: HashMap::new(),
+        }
+    }
+}
+
+impl<P: Eq + Hash> PingTracker<P> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create the ping to send to `peer`. A previous ping still waiting for its pong is forgotten.
+    pub fn ping(&mut self, peer: P, now: Instant) -> Ping {
+        let ping = Ping::random();
+        self.outstanding.insert(peer, (ping.nonce, now));
+        ping
+    }
+
+    /// Match a pong against the outstanding ping of `peer`, returns the round trip time if it does.
+    pub fn pong(&mut self, peer: &P, pong: &Pong, now: Instant) -> Option<Duration> {
+        let (nonce, _) = self.outstanding.get(peer)?;
+        if *nonce != pong.nonce {
+            return None;
+        }
+        let (peer, (_, sent)) = self.outstanding.remove_entry(peer)?;
+
+        let latency = now.saturating_duration_since(sent);
+        self.latencies.insert(peer, latency);
+
+        Some(latency)
+    }
+
+    /// Last measured round trip time.
+    pub fn latency(&self, peer: &P) -> Option<Duration> {
+        self.latencies.get(peer).copied()
+    }
+
+    /// When the ping waiting for a pong was sent, to detect unresponsive peers.
+    pub fn outstanding_since(&self, peer: &P) -> Option<Instant> {
+        self.outstanding.get(peer).map(|(_, sent)| *sent)
+    }
+
+    pub fn remove(&mut self, peer: &P) {
+        self.outstanding.remove(peer);
+        self.latencies.remove(peer);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ErrorKind;
+
+    #[test]
+    fn test_ping_bip31() {
+        let ping = Ping { nonce: 1 };
+
+        assert_eq!(ping.serialize_for_version(70015), [1, 0, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(ping.serialize_for_version(BIP31_VERSION), []);
+        assert_eq!(Ping::deserialize(&[]).unwrap(), Ping { nonce: 0 });
+        assert_eq!(
+            Ping::deserialize(&ping.serialize()).unwrap().pong(),
+            Pong { nonce: 1 }
+        );
+        assert!(Pong::deserialize(&[]).is_err());
+
+        let mut trailing = ping.serialize();
+        trailing.push(0);
+        let err = Ping::deserialize(&trailing).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+        let err = Pong::deserialize(&trailing).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+
+    #[test]
+    fn test_ping_tracker() {
+        let start = Instant::now();
+        let mut tracker: PingTracker<u32> = PingTracker::new();
+
+        let ping = tracker.ping(1, start);
+        tracker.ping(2, start);
+        assert_eq!(tracker.outstanding_since(&1), Some(start));
+
+        // Pong for another ping or from another peer
+        let wrong_pong = Pong {
+            nonce: ping.nonce.wrapping_add(1),
+        };
+        assert_eq!(tracker.pong(&1, &wrong_pong, start), None);
+        assert_eq!(tracker.pong(&3, &ping.pong(), start), None);
+
+        let now = start + Duration::from_millis(120);
+        assert_eq!(
+            tracker.pong(&1, &ping.pong(), now),
+            Some(Duration::from_millis(120))
+        );
+        assert_eq!(tracker.latency(&1), Some(Duration::from_millis(120)));
+        assert_eq!(tracker.outstanding_since(&1), None);
+        assert_eq!(tracker.latency(&2), None);
+
+        // Answered only once
+        assert_eq!(tracker.pong(&1, &ping.pong(), now), None);
+    }
+}
@@ -0,0 +1,288 @@
+use crate::error::ValidationError;
+use std::cmp::Ordering;
+use std::ops::{Add, Not, Shl, Shr, Sub};
+
+/// 256 bits unsigned integer used for targets and chain work. Limbs are stored least significant first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct U256(pub [u64; 4]);
+
+impl U256 {
+    pub const ZERO: U256 = U256([0, 0, 0, 0]);
+    pub const ONE: U256 = U256([1, 0, 0, 0]);
+    pub const MAX: U256 = U256([u64::MAX; 4]);
+
+    pub fn from_u64(value: u64) -> Self {
+        Self([value, 0, 0, 0])
+    }
+
+    /// Interpret 32 bytes in little endian, the order in which hashes are serialized.
+    pub fn from_le_bytes(bytes: [u8; 32]) -> Self {
+        let mut limbs = [0u64; 4];
+        for (i, limb) in limbs.iter_mut().enumerate() {
+            *limb = u64::from_le_bytes(bytes[i * 8..(i + 1) * 8].try_into().unwrap());
+        }
+        Self(limbs)
+    }
+
+    pub fn to_le_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        for (i, limb) in self.0.iter().enumerate() {
+            bytes[i * 8..(i + 1) * 8].copy_from_slice(&limb.to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn low_u64(&self) -> u64 {
+        self.0[0]
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0 == [0; 4]
+    }
+
+    /// Number of significant bits.
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+
+    fn bit(&self, index: u32) -> bool {
+        (self.0[(index / 64) as usize] >> (index % 64)) & 1 == 1
+    }
+
+    /// Decode the compact representation used in the block header `bits` field. Returns the target
+    /// and whether the encoding is negative or overflows 256 bits, same as Bitcoin Core `SetCompact`.
+    pub fn from_compact(compact: u32) -> (Self, bool, bool) {
+        let size = compact >> 24;
+        let mut word = compact & 0x007fffff;
+
+        let target = if size <= 3 {
+            word >>= 8 * (3 - size);
+            Self::from_u64(word as u64)
+        } else {
+            Self::from_u64(word as u64) << (8 * (size - 3))
+        };
+
+        let negative = word != 0 && (compact & 0x00800000) != 0;
+        let overflow =
+            word != 0 && (size > 34 || (word > 0xff && size > 33) || (word > 0xffff && size > 32));
+
+        (target, negative, overflow)
+    }
+
+    pub fn to_compact(&self) -> u32 {
+        let mut size = self.bits().div_ceil(8);
+        let mut compact = if size <= 3 {
+            (self.low_u64() << (8 * (3 - size))) as u32
+        } else {
+            (*self >> (8 * (size - 3))).low_u64() as u32
+        };
+
+        // The 0x00800000 bit is the sign, use one more byte instead
+        if compact & 0x00800000 != 0 {
+            compact >>= 8;
+            size += 1;
+        }
+
+        compact | (size << 24)
+    }
+
+    pub fn checked_div(self, divisor: Self) -> Option<Self> {
+        if divisor.is_zero() {
+            return None;
+        }
+
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for i in (0..self.bits()).rev() {
+            remainder = remainder << 1;
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if remainder >= divisor {
+                remainder = remainder - divisor;
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+
+        Some(quotient)
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                ordering => return ordering,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Wrapping addition, like the arith_uint256 of Bitcoin Core
+impl Add for U256 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        let mut result = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in result.iter_mut().enumerate() {
+            let (sum, overflow1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, overflow2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = overflow1 || overflow2;
+        }
+        Self(result)
+    }
+}
+
+// Wrapping subtraction
+impl Sub for U256 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        self + !other + Self::ONE
+    }
+}
+
+impl Not for U256 {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        Self(self.0.map(|limb| !limb))
+    }
+}
+
+impl Shl<u32> for U256 {
+    type Output = Self;
+
+    fn shl(self, shift: u32) -> Self {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for (i, limb) in result.iter_mut().enumerate().skip(limbs) {
+            *limb = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                *limb |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        Self(result)
+    }
+}
+
+impl Shr<u32> for U256 {
+    type Output = Self;
+
+    fn shr(self, shift: u32) -> Self {
+        let mut result = [0u64; 4];
+        let limbs = (shift / 64) as usize;
+        let bits = shift % 64;
+        for (i, limb) in result
+            .iter_mut()
+            .enumerate()
+            .take(4usize.saturating_sub(limbs))
+        {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        Self(result)
+    }
+}
+
+/// Target encoded by `bits`, `None` if the encoding is negative, overflows or is zero.
+pub fn target_from_compact(bits: u32) -> Option<U256> {
+    let (target, negative, overflow) = U256::from_compact(bits);
+    if negative || overflow || target.is_zero() {
+        return None;
+    }
+    Some(target)
+}
+
+/// Expected number of hashes to find a block with this target: 2**256 / (target + 1).
+/// Invalid targets are worth no work.
+pub fn work_from_compact(bits: u32) -> U256 {
+    match target_from_compact(bits) {
+        // 2**256 does not fit, but 2**256 / (target + 1) == ~target / (target + 1) + 1
+        Some(target) => (!target).checked_div(target + U256::ONE).unwrap() + U256::ONE,
+        None => U256::ZERO,
+    }
+}
+
+/// Check a proof of work hash (in serialized byte order) against the target encoded by `bits`.
+pub fn check_pow(hash: [u8; 32], bits: u32) -> Result<(), ValidationError> {
+    let target = match target_from_compact(bits) {
+        Some(target) => target,
+        None => return Err(ValidationError(format!("Invalid target bits {bits:#010x}"))),
+    };
+
+    if U256::from_le_bytes(hash) > target {
+        return Err(ValidationError(
+            "Proof of work hash above target".to_owned(),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_compact() {
+        assert_eq!(U256::from_compact(0x01003456), (U256::ZERO, false, false));
+        assert_eq!(
+            U256::from_compact(0x01123456),
+            (U256::from_u64(0x12), false, false)
+        );
+        assert!(U256::from_compact(0x04923456).1);
+        assert_eq!(
+            U256::from_compact(0x05009234),
+            (U256::from_u64(0x92340000), false, false)
+        );
+        assert!(U256::from_compact(0xff123456).2);
+
+        let (target, _, _) = U256::from_compact(0x1d00ffff);
+        assert_eq!(target, U256([0, 0, 0, 0x00000000ffff0000]));
+    }
+
+    #[test]
+    fn test_to_compact() {
+        assert_eq!(U256::from_u64(0x12).to_compact(), 0x01120000);
+        assert_eq!(U256::from_u64(0x80).to_compact(), 0x02008000);
+        assert_eq!(U256::from_u64(0x92340000).to_compact(), 0x05009234);
+        assert_eq!(U256::from_compact(0x1d00ffff).0.to_compact(), 0x1d00ffff);
+        assert_eq!(U256::from_compact(0x1b0404cb).0.to_compact(), 0x1b0404cb);
+    }
+
+    #[test]
+    fn test_work_from_compact() {
+        assert_eq!(work_from_compact(0x1d00ffff), U256::from_u64(0x100010001));
+        assert_eq!(work_from_compact(0x207fffff), U256::from_u64(2));
+        assert_eq!(work_from_compact(0x04923456), U256::ZERO);
+    }
+
+    #[test]
+    fn test_check_pow() {
+        let mut hash = [0xffu8; 32];
+        assert!(check_pow(hash, 0x1d00ffff).is_err());
+
+        hash[26..32].copy_from_slice(&[0, 0, 0, 0, 0, 0]);
+        assert!(check_pow(hash, 0x1d00ffff).is_ok());
+        assert!(check_pow(hash, 0x01003456).is_err());
+    }
+}
@@ -0,0 +1,98 @@
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::DeserializeError;
+use std::io::{self, Read, Write};
+
+// Reject codes (BIP61)
+pub const REJECT_MALFORMED: u8 = 0x01;
+pub const REJECT_INVALID: u8 = 0x10;
+pub const REJECT_OBSOLETE: u8 = 0x11;
+pub const REJECT_DUPLICATE: u8 = 0x12;
+pub const REJECT_NONSTANDARD: u8 = 0x40;
+pub const REJECT_DUST: u8 = 0x41;
+pub const REJECT_INSUFFICIENTFEE: u8 = 0x42;
+pub const REJECT_CHECKPOINT: u8 = 0x43;
+
+// Maximum length of the reason (MAX_REJECT_MESSAGE_LENGTH in Bitcoin Core)
+pub const MAX_REJECT_MESSAGE_LENGTH: u64 = 111;
+// Rejected message is a command name
+const MAX_COMMAND_LENGTH: u64 = 12;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reject {
+    // command of the rejected message
+    pub message: String,
+    pub code: u8,
+    pub reason: String,
+    // extra data, usually the hash of the rejected tx or block
+    pub data: Vec<u8>,
+}
+
+impl Reject {
+    pub fn serialize(&self) -> Vec<u8> {
+        encode::serialize(self)
+    }
+
+    /// The extra data has no length, it is the rest of the payload. This is why `Reject` is only
+    /// decoded from a whole payload and does not implement `Decodable`.
+    pub fn deserialize(bytes: &[u8]) -> Result<Self, DeserializeError> {
+        let mut reader = encode::Reader::new(bytes);
+        let (message, code, reason) = encode::within("Reject", || {
+            Ok((
+                Self::decode_string(&mut reader, MAX_COMMAND_LENGTH)?,
+                u8::decode(&mut reader)?,
+                Self::decode_string(&mut reader, MAX_REJECT_MESSAGE_LENGTH)?,
+            ))
+        })
+        .map_err(|e| e.locate(reader.position()))?;
+
+        Ok(Self {
+            message,
+            code,
+            reason,
+            data: reader.get_ref().to_vec(),
+        })
+    }
+
+    fn decode_string<R: Read + ?Sized>(
+        reader: &mut R,
+        max_size: u64,
+    ) -> Result<String, DeserializeError> {
+        let buf = encode::read_var_bytes(reader, max_size)?;
+        Ok(String::from_utf8(buf)?)
+    }
+}
+
+impl Encodable for Reject {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = encode::encode_var_bytes(self.message.as_bytes(), writer)?;
+        size += self.code.encode(writer)?;
+        size += encode::encode_var_bytes(self.reason.as_bytes(), writer)?;
+        writer.write_all(&self.data)?;
+        Ok(size + self.data.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reject_serialize() {
+        let reject = Reject {
+            message: "tx".to_owned(),
+            code: REJECT_DUPLICATE,
+            reason: "txn-already-known".to_owned(),
+            data: vec![0xab; 32],
+        };
+        let raw = reject.serialize();
+
+        assert_eq!(&raw[0..4], [2, b't', b'x', REJECT_DUPLICATE]);
+        assert_eq!(Reject::deserialize(&raw).unwrap(), reject);
+
+        let err = Reject::deserialize(&raw[..10]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Unexpected end of input at byte 10 in Reject"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::fmt;
+use std::ops::{BitOr, BitOrAssign};
+
+/// Services bitfield advertised in `version` and in relayed addresses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ServiceFlags(pub u64);
+
+impl ServiceFlags {
+    pub const NONE: ServiceFlags = ServiceFlags(0);
+    // Full node serving the whole block chain
+    pub const NETWORK: ServiceFlags = ServiceFlags(1 << 0);
+    // BIP64 getutxo (not supported by Bitcoin Core)
+    pub const GETUTXO: ServiceFlags = ServiceFlags(1 << 1);
+    // BIP111 bloom filters
+    pub const BLOOM: ServiceFlags = ServiceFlags(1 << 2);
+    // BIP144 segregated witness
+    pub const WITNESS: ServiceFlags = ServiceFlags(1 << 3);
+    // BIP157 compact block filters
+    pub const COMPACT_FILTERS: ServiceFlags = ServiceFlags(1 << 6);
+    // BIP159 pruned node serving the last 288 blocks
+    pub const NETWORK_LIMITED: ServiceFlags = ServiceFlags(1 << 10);
+
+    const NAMES: [(ServiceFlags, &'static str); 6] = [
+        (ServiceFlags::NETWORK, "NETWORK"),
+        (ServiceFlags::GETUTXO, "GETUTXO"),
+        (ServiceFlags::BLOOM, "BLOOM"),
+        (ServiceFlags::WITNESS, "WITNESS"),
+        (ServiceFlags::COMPACT_FILTERS, "COMPACT_FILTERS"),
+        (ServiceFlags::NETWORK_LIMITED, "NETWORK_LIMITED"),
+    ];
+
+    /// True if all the flags of `flags` are set.
+    pub fn has(&self, flags: ServiceFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+
+    pub fn add(&mut self, flags: ServiceFlags) -> ServiceFlags {
+        self.0 |= flags.0;
+        *self
+    }
+
+    pub fn remove(&mut self, flags: ServiceFlags) -> ServiceFlags {
+        self.0 &= !flags.0;
+        *self
+    }
+}
+
+impl BitOr for ServiceFlags {
+    type Output = ServiceFlags;
+
+    fn bitor(self, rhs: ServiceFlags) -> ServiceFlags {
+        ServiceFlags(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for ServiceFlags {
+    fn bitor_assign(&mut self, rhs: ServiceFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+// e.g. "ServiceFlags(NETWORK|WITNESS|0x100)", unknown bits are displayed in hexadecimal
+impl fmt::Display for ServiceFlags {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == ServiceFlags::NONE {
+            return write!(f, "ServiceFlags(NONE)");
+        }
+
+        let mut remaining = *self;
+        let mut names: Vec<String> = vec![];
+        for (flag, name) in ServiceFlags::NAMES {
+            if remaining.has(flag) {
+                names.push(name.to_owned());
+                remaining.remove(flag);
+            }
+        }
+        if remaining != ServiceFlags::NONE {
+            names.push(format!("{:#x}", remaining.0));
+        }
+
+        write!(f, "ServiceFlags({})", names.join("|"))
+    }
+}
+
+impl From<u64> for ServiceFlags {
+    fn from(value: u64) -> Self {
+        ServiceFlags(value)
+    }
+}
+
+impl From<ServiceFlags> for u64 {
+    fn from(value: ServiceFlags) -> Self {
+        value.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_service_flags() {
+        let mut flags = ServiceFlags::NONE;
+        assert!(!flags.has(ServiceFlags::NETWORK));
+        assert!(flags.has(ServiceFlags::NONE));
+
+        flags.add(ServiceFlags::NETWORK | ServiceFlags::WITNESS);
+        assert!(flags.has(ServiceFlags::NETWORK));
+        assert!(flags.has(ServiceFlags::NETWORK | ServiceFlags::WITNESS));
+        assert!(!flags.has(ServiceFlags::NETWORK | ServiceFlags::BLOOM));
+
+        flags.remove(ServiceFlags::NETWORK);
+        assert_eq!(flags, ServiceFlags::WITNESS);
+    }
+
+    #[test]
+    fn test_service_flags_display() {
+        assert_eq!(ServiceFlags::NONE.to_string(), "ServiceFlags(NONE)");
+        assert_eq!(
+            ServiceFlags(0x409).to_string(),
+            "ServiceFlags(NETWORK|WITNESS|NETWORK_LIMITED)"
+        );
+        assert_eq!(ServiceFlags(0x5).to_string(), "ServiceFlags(NETWORK|BLOOM)");
+        assert_eq!(
+            (ServiceFlags::NETWORK | ServiceFlags(1 << 24)).to_string(),
+            "ServiceFlags(NETWORK|0x1000000)"
+        );
+    }
+}
@@ -1,7 +1,14 @@
-use crate::error::DeserializeError;
+use crate::encode::{self, CompactSize, Decodable, Encodable};
+use crate::error::{DeserializeError, ErrorKind};
+use crate::hash::{Txid, Wtxid};
 use crate::utils;
-use std::io::{Cursor, Read};
-use varint::VarInt;
+use std::io::{self, Read, Write};
+
+// BIP144 marker and flag bytes placed after the version in the extended serialization
+const SEGWIT_MARKER: u8 = 0x00;
+const SEGWIT_FLAG: u8 = 0x01;
+// Maximum size of a script that can be executed (MAX_SCRIPT_SIZE in Bitcoin Core)
+pub const MAX_SCRIPT_SIZE: u64 = 10_000;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Tx {
@@ -12,79 +19,135 @@ pub struct Tx {
 }
 
 impl Tx {
-    pub fn hash(&self) -> [u8; 32] {
+    /// Alias of `txid`, the hash used to reference the transaction in outpoints and inventories.
+    pub fn hash(&self) -> Txid {
+        self.txid()
+    }
+
+    /// Hash of the legacy serialization (witness data excluded).
+    pub fn txid(&self) -> Txid {
+        let tx = &self.serialize_without_witness();
+        Txid(utils::double_hash(tx))
+    }
+
+    /// Hash of the full serialization (witness data included). Equal to `txid` for non witness transactions.
+    pub fn wtxid(&self) -> Wtxid {
         let tx = &self.serialize();
-        utils::double_hash(tx)
+        Wtxid(utils::double_hash(tx))
     }
 
+    pub fn has_witness(&self) -> bool {
+        self.tx_ins.iter().any(|txin| !txin.witness.is_empty())
+    }
+
+    /// Serialize the transaction using the BIP144 extended format when one of the inputs carries a witness.
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
+        encode::serialize(self)
+    }
 
-        result.extend(self.version.to_le_bytes());
-        result.extend(VarInt::encode(self.tx_ins.len() as u64).unwrap());
-        self.tx_ins
-            .iter()
-            .for_each(|txin| result.extend(txin.serialize()));
-        result.extend(VarInt::encode(self.tx_outs.len() as u64).unwrap());
-        self.tx_outs
-            .iter()
-            .for_each(|txout| result.extend(txout.serialize()));
-        result.extend(self.lock_time.to_le_bytes());
+    pub fn serialize_without_witness(&self) -> Vec<u8> {
+        encode::serialize(&WithoutWitness(self))
+    }
 
-        result
+    fn encode_without_witness<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.version.encode(writer)?;
+        size += self.tx_ins.encode(writer)?;
+        size += self.tx_outs.encode(writer)?;
+        size += self.lock_time.encode(writer)?;
+        Ok(size)
     }
 
     // We only know the size of the tx after deserializing it. To know when the next tx start we have to return the value
     pub fn deserialize_with_size(bytes: &[u8]) -> Result<(Tx, u64), DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize_with_size(bytes)
+    }
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let version = i32::from_le_bytes(buf);
+    pub fn deserialize(bytes: &[u8]) -> Result<Tx, DeserializeError> {
+        encode::deserialize_exact(bytes, "Tx")
+    }
+}
 
-        // Deserialize tx inputs
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
+// Legacy serialization of a transaction, the one hashed by the txid
+struct WithoutWitness<'a>(&'a Tx);
 
-        let mut tx_ins: Vec<TxIn> = vec![];
-        for _ in 0..count {
-            let (tx_in, size) = TxIn::deserialize_with_size(cur.split().1)?;
-            cur.set_position(cur.position() + size);
+impl Encodable for WithoutWitness<'_> {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        self.0.encode_without_witness(writer)
+    }
+}
 
-            tx_ins.push(tx_in);
+impl Encodable for Tx {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        if !self.has_witness() {
+            return self.encode_without_witness(writer);
         }
 
-        // Deserialize tx ouputs
-        let count = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(count)? as u64;
-        cur.set_position(cur.position() + varint_size);
-
-        let mut tx_outs: Vec<TxOut> = vec![];
-        for _ in 0..count {
-            let (tx_out, size) = TxOut::deserialize_with_size(cur.split().1)?;
-            cur.set_position(cur.position() + size);
-
-            tx_outs.push(tx_out);
+        let mut size = self.version.encode(writer)?;
+        size += SEGWIT_MARKER.encode(writer)?;
+        size += SEGWIT_FLAG.encode(writer)?;
+        size += self.tx_ins.encode(writer)?;
+        size += self.tx_outs.encode(writer)?;
+        for tx_in in &self.tx_ins {
+            size += CompactSize(tx_in.witness.len() as u64).encode(writer)?;
+            for item in &tx_in.witness {
+                size += encode::encode_var_bytes(item, writer)?;
+            }
         }
+        size += self.lock_time.encode(writer)?;
+        Ok(size)
+    }
+}
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let lock_time = u32::from_le_bytes(buf);
-
-        Ok((
-            Self {
+impl Decodable for Tx {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Tx", || {
+            let version = i32::decode(reader)?;
+
+            let mut tx_ins: Vec<TxIn> = Vec::decode(reader)?;
+
+            // An empty input list is the BIP144 marker, it is followed by the flag and the real inputs
+            let mut flag = 0u8;
+            if tx_ins.is_empty() {
+                flag = u8::decode(reader)?;
+                if flag != SEGWIT_FLAG {
+                    return Err(DeserializeError::new(ErrorKind::InvalidValue {
+                        field: "flag",
+                    }));
+                }
+                tx_ins = Vec::decode(reader)?;
+            }
+
+            let tx_outs: Vec<TxOut> = Vec::decode(reader)?;
+
+            // Deserialize witnesses, one stack per input
+            if flag == SEGWIT_FLAG {
+                for tx_in in tx_ins.iter_mut() {
+                    let count = encode::read_count(reader, u64::MAX)?;
+                    for _ in 0..count {
+                        // Only bounded by the input, witness items are not limited to MAX_SCRIPT_SIZE
+                        tx_in
+                            .witness
+                            .push(encode::read_var_bytes(reader, u64::MAX)?);
+                    }
+                }
+
+                // Same rule as Bitcoin Core: the extended format is only allowed when there is witness data
+                if tx_ins.iter().all(|tx_in| tx_in.witness.is_empty()) {
+                    return Err(DeserializeError::new(ErrorKind::InvalidValue {
+                        field: "witness",
+                    }));
+                }
+            }
+
+            let lock_time = u32::decode(reader)?;
+
+            Ok(Self {
                 version,
                 tx_ins,
                 tx_outs,
                 lock_time,
-            },
-            cur.position(),
-        ))
-    }
-
-    pub fn deserialize(bytes: &[u8]) -> Result<Tx, DeserializeError> {
-        Ok(Self::deserialize_with_size(bytes)?.0)
+            })
+        })
     }
 }
 
@@ -93,84 +156,84 @@ pub struct TxIn {
     pub previous_output: Outpoint,
     pub signature_script: Vec<u8>,
     pub sequence: u32,
+    // witness stack (BIP141), not part of the input serialization but of the transaction one
+    pub witness: Vec<Vec<u8>>,
 }
 
 impl TxIn {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-
-        result.extend(self.previous_output.serialize());
-        result.extend(VarInt::encode(self.signature_script.len() as u64).unwrap());
-        result.extend(&self.signature_script);
-        result.extend(self.sequence.to_le_bytes());
-
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize_with_size(bytes: &[u8]) -> Result<(TxIn, u64), DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize_with_size(bytes)
+    }
+
+    pub fn deserialize(bytes: &[u8]) -> Result<TxIn, DeserializeError> {
+        encode::deserialize(bytes)
+    }
+}
 
-        let mut buf = [0u8; 36];
-        cur.read_exact(&mut buf)?;
-        let previous_output = Outpoint::deserialize(&buf)?;
+impl Encodable for TxIn {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.previous_output.encode(writer)?;
+        size += encode::encode_var_bytes(&self.signature_script, writer)?;
+        size += self.sequence.encode(writer)?;
+        Ok(size)
+    }
+}
 
-        let input_size = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(input_size)? as u64;
-        cur.set_position(cur.position() + varint_size);
+impl Decodable for TxIn {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("TxIn", || {
+            let previous_output = Outpoint::decode(reader)?;
 
-        let mut buf = vec![0; input_size as usize];
-        cur.read_exact(&mut buf)?;
-        let signature_script = buf;
+            // A larger script would fail to execute, so no valid transaction carries one
+            let signature_script = encode::read_var_bytes(reader, MAX_SCRIPT_SIZE)?;
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let sequence = u32::from_le_bytes(buf);
+            let sequence = u32::decode(reader)?;
 
-        Ok((
-            Self {
+            Ok(Self {
                 previous_output,
                 signature_script,
                 sequence,
-            },
-            cur.position(),
-        ))
-    }
-
-    pub fn deserialize(bytes: &[u8]) -> Result<TxIn, DeserializeError> {
-        Ok(Self::deserialize_with_size(bytes)?.0)
+                witness: vec![],
+            })
+        })
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Outpoint {
-    pub previous_hash: [u8; 32],
+    pub previous_hash: Txid,
     pub index: u32,
 }
 
 impl Outpoint {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-
-        result.extend(self.previous_hash.to_vec());
-        result.extend(self.index.to_le_bytes());
-
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Outpoint, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
-
-        let mut buf = [0u8; 32];
-        cur.read_exact(&mut buf)?;
-        let previous_hash = buf;
+        encode::deserialize(bytes)
+    }
+}
 
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let index = u32::from_le_bytes(buf);
+impl Encodable for Outpoint {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.previous_hash.encode(writer)?;
+        size += self.index.encode(writer)?;
+        Ok(size)
+    }
+}
 
-        Ok(Self {
-            previous_hash,
-            index,
+impl Decodable for Outpoint {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Outpoint", || {
+            Ok(Self {
+                previous_hash: Decodable::decode(reader)?,
+                index: Decodable::decode(reader)?,
+            })
         })
     }
 }
@@ -183,35 +246,36 @@ pub struct TxOut {
 
 impl TxOut {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = vec![];
-
-        result.extend(self.value.to_le_bytes());
-        result.extend(VarInt::encode(self.pk_script.len() as u64).unwrap());
-        result.extend(&self.pk_script);
-
-        result
+        encode::serialize(self)
     }
 
     pub fn deserialize_with_size(bytes: &[u8]) -> Result<(TxOut, u64), DeserializeError> {
-        let mut cur = Cursor::new(bytes);
+        encode::deserialize_with_size(bytes)
+    }
 
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let value = i64::from_le_bytes(buf);
+    pub fn deserialize(bytes: &[u8]) -> Result<TxOut, DeserializeError> {
+        encode::deserialize(bytes)
+    }
+}
 
-        let script_size = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(script_size)? as u64;
-        cur.set_position(cur.position() + varint_size);
+impl Encodable for TxOut {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.value.encode(writer)?;
+        size += encode::encode_var_bytes(&self.pk_script, writer)?;
+        Ok(size)
+    }
+}
 
-        let mut buf = vec![0; script_size as usize];
-        cur.read_exact(&mut buf)?;
-        let pk_script = buf;
+impl Decodable for TxOut {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("TxOut", || {
+            let value = i64::decode(reader)?;
 
-        Ok((Self { value, pk_script }, cur.position()))
-    }
+            // Only bounded by the input: outputs with scripts over MAX_SCRIPT_SIZE are valid, just unspendable
+            let pk_script = encode::read_var_bytes(reader, u64::MAX)?;
 
-    pub fn deserialize(bytes: &[u8]) -> Result<TxOut, DeserializeError> {
-        Ok(Self::deserialize_with_size(bytes)?.0)
+            Ok(Self { value, pk_script })
+        })
     }
 }
 
@@ -228,5 +292,65 @@ mod tests {
         let raw_tx_bis = tx.serialize();
 
         assert_eq!(raw_tx_bis, raw_tx);
+
+        let mut trailing = raw_tx.clone();
+        trailing.push(0);
+        let err = Tx::deserialize(&trailing).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::TrailingBytes(1));
+    }
+
+    #[test]
+    fn test_deserialize_segwit_tx() {
+        // Native P2WPKH example from BIP143
+        let raw_tx = hex::decode("01000000000102fff7f7881a8099afa6940d42d1e7f6362bec38171ea3edf433541db4e4ad969f00000000494830450221008b9d1dc26ba6a9cb62127b02742fa9d754cd3bebf337f7a55d114c8e5cdd30be022040529b194ba3f9281a99f2b1c0a19c0489bc22ede944ccf4ecbab4cc618ef3ed01eeffffffef51e1b804cc89d182d279655c3aa89e815b1b309fe287d9b2b55d57b90ec68a0100000000ffffffff02202cb206000000001976a9148280b37df378db99f66f85c95a783a76ac7a6d5988ac9093510d000000001976a9143bde42dbee7e4dbe6a21b2d50ce2f0167faa815988ac000247304402203609e17b84f6a7d30c80bfa610b5b4542f32a8a0d5447a12fb1366d7f01cc44a0220573a954c4518331561406f90300e8f3358f51928d43c212a8caed02de67eebee0121025476c2e83188368da1ff3e292e7acafcdb3566bb0ad253f62fc70f07aeee635711000000").unwrap();
+
+        let (tx, size) = Tx::deserialize_with_size(&raw_tx).unwrap();
+
+        assert_eq!(size, raw_tx.len() as u64);
+        assert_eq!(tx.tx_ins.len(), 2);
+        assert_eq!(tx.tx_outs.len(), 2);
+        assert!(tx.tx_ins[0].witness.is_empty());
+        assert_eq!(tx.tx_ins[1].witness.len(), 2);
+        assert_eq!(tx.lock_time, 17);
+        assert_eq!(tx.serialize(), raw_tx);
+
+        // The txid commits to the legacy serialization only
+        let legacy = Tx::deserialize(&tx.serialize_without_witness()).unwrap();
+        assert!(!legacy.has_witness());
+        assert_eq!(legacy.txid(), tx.txid());
+        assert_eq!(legacy.wtxid().0, tx.txid().0);
+        assert_ne!(tx.wtxid().0, tx.txid().0);
+    }
+
+    #[test]
+    fn test_deserialize_superfluous_witness() {
+        // Extended format with an empty witness stack for the only input
+        let raw_tx = hex::decode("0100000000010100000000000000000000000000000000000000000000000000000000000000000000000000ffffffff0100000000000000000000000000").unwrap();
+
+        assert!(Tx::deserialize(&raw_tx).is_err());
+    }
+
+    #[test]
+    fn test_deserialize_oversized_lengths() {
+        // 0xffffffff inputs announced in a 10 bytes transaction
+        let raw_tx = hex::decode("01000000feffffffff00").unwrap();
+        assert!(Tx::deserialize(&raw_tx).is_err());
+
+        // Signature script of 10001 bytes
+        let mut raw_tx = hex::decode("0100000001").unwrap();
+        raw_tx.extend([0u8; 36]);
+        raw_tx.extend([0xfd, 0x11, 0x27]);
+        raw_tx.extend(vec![0u8; 10_001]);
+        raw_tx.extend(hex::decode("ffffffff0000000000").unwrap());
+        assert!(Tx::deserialize(&raw_tx).is_err());
+
+        // Input count of 1 encoded on 3 bytes
+        let raw_tx = hex::decode("01000000fd0100").unwrap();
+        let err = Tx::deserialize(&raw_tx).unwrap_err();
+        assert_eq!(err.kind, ErrorKind::NonCanonicalVarInt);
+
+        // pk script announcing 2^64 - 1 bytes
+        let raw_tx_out = hex::decode("0000000000000000ffffffffffffffffff00").unwrap();
+        assert!(TxOut::deserialize(&raw_tx_out).is_err());
     }
 }
@@ -1,10 +1,13 @@
 use sha2::{Digest, Sha256};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn double_hash(message: &Vec<u8>) -> [u8; 32] {
     let mut digest = Sha256::digest(message);
     digest = Sha256::digest(digest);
 
-    digest.try_into().unwrap()
+    digest.into()
 }
 
 pub fn checksum(message: &Vec<u8>) -> [u8; 4] {
@@ -12,3 +15,14 @@ pub fn checksum(message: &Vec<u8>) -> [u8; 4] {
 
     hash[0..4].try_into().unwrap()
 }
+
+// Random enough for ping and version nonces without pulling a rng crate: each RandomState
+// is seeded with fresh random keys by the standard library
+pub fn random_nonce() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    hasher.write_u128(now.as_nanos());
+    hasher.finish()
+}
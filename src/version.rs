@@ -1,97 +1,116 @@
 use crate::address::Address;
-use crate::error::DeserializeError;
-use std::io::{Cursor, Read};
-use varint::VarInt;
-
+use crate::encode::{self, Decodable, Encodable};
+use crate::error::{DeserializeError, ErrorKind};
+use crate::services::ServiceFlags;
+use std::io::{self, Read, Write};
+
+// Protocol versions from which the optional fields are sent (see `Version`)
+pub const ADDR_FROM_VERSION: i32 = 106;
+pub const START_HEIGHT_VERSION: i32 = 209;
+pub const RELAY_VERSION: i32 = 70001;
+// Maximum length of the user agent (MAX_SUBVERSION_LENGTH in Bitcoin Core)
+pub const MAX_USER_AGENT_LENGTH: u64 = 256;
+
+/// Fields added after the first protocol versions are only present when the sender version is
+/// high enough: `addr_trans`, `nonce` and `user_agent` from 106, `start_height` from 209 and
+/// `relay` from 70001. Missing fields get their default value, `relay` defaults to true.
 #[derive(Debug, Clone, PartialEq)]
 pub struct Version {
-    pub version: u32,
-    pub services: u64,
+    pub version: i32,
+    pub services: ServiceFlags,
     pub timestamp: u64,
     pub addr_recv: Address,
     pub addr_trans: Address,
     pub nonce: u64,
     pub user_agent: String,
-    pub start_height: u32,
+    pub start_height: i32,
     pub relay: bool,
 }
 
 impl Version {
     pub fn serialize(&self) -> Vec<u8> {
-        let mut result: Vec<u8> = Vec::new();
-        result.extend(self.version.to_le_bytes());
-        result.extend(self.services.to_le_bytes());
-        result.extend(self.timestamp.to_le_bytes());
-        result.extend(self.addr_recv.serialize());
-        result.extend(self.addr_trans.serialize());
-        result.extend(self.nonce.to_le_bytes());
-        result.extend(VarInt::encode(self.user_agent.len() as u64).unwrap());
-        result.extend(self.user_agent.as_bytes());
-        result.extend(self.start_height.to_le_bytes());
-        result.push(self.relay as u8);
-        result
+        encode::serialize(self)
     }
 
+    /// Trailing bytes are accepted, peers with a newer protocol version may append fields.
     pub fn deserialize(bytes: &[u8]) -> Result<Self, DeserializeError> {
-        let mut cur = Cursor::new(bytes);
-
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let version = u32::from_le_bytes(buf);
-
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let services = u64::from_le_bytes(buf);
-
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let timestamp = u64::from_le_bytes(buf);
-
-        let mut buf = [0u8; 26];
-        cur.read_exact(&mut buf)?;
-        let addr_recv = Address::deserialize(&buf)?;
-
-        let mut buf = [0u8; 26];
-        cur.read_exact(&mut buf)?;
-        let addr_trans = Address::deserialize(&buf)?;
-
-        let mut buf = [0u8; 8];
-        cur.read_exact(&mut buf)?;
-        let nonce = u64::from_le_bytes(buf);
-
-        let varint = VarInt::decode(cur.split().1)?;
-        let varint_size = VarInt::get_size(varint)? as u64;
-        cur.set_position(cur.position() + varint_size);
-
-        let mut buf = vec![0; varint as usize];
-        cur.read_exact(&mut buf)?;
-        let user_agent = String::from_utf8(buf)?;
-
-        let mut buf = [0u8; 4];
-        cur.read_exact(&mut buf)?;
-        let start_height = u32::from_le_bytes(buf);
-
-        // FIXME: Verify there is a a last byte
-        let relay = match cur.split().1[0] {
-            0 => false,
-            1 => true,
-            _ => {
-                return Err(DeserializeError(
-                    "Failed to deserialize relay value".to_owned(),
-                ))
+        encode::deserialize(bytes)
+    }
+}
+
+impl Encodable for Version {
+    fn encode<W: Write + ?Sized>(&self, writer: &mut W) -> io::Result<usize> {
+        let mut size = self.version.encode(writer)?;
+        size += self.services.0.encode(writer)?;
+        size += self.timestamp.encode(writer)?;
+        size += self.addr_recv.encode(writer)?;
+        if self.version >= ADDR_FROM_VERSION {
+            size += self.addr_trans.encode(writer)?;
+            size += self.nonce.encode(writer)?;
+            size += encode::encode_var_bytes(self.user_agent.as_bytes(), writer)?;
+        }
+        if self.version >= START_HEIGHT_VERSION {
+            size += self.start_height.encode(writer)?;
+        }
+        if self.version >= RELAY_VERSION {
+            size += self.relay.encode(writer)?;
+        }
+        Ok(size)
+    }
+}
+
+impl Decodable for Version {
+    fn decode<R: Read + ?Sized>(reader: &mut R) -> Result<Self, DeserializeError> {
+        encode::within("Version", || {
+            let version = i32::decode(reader)?;
+            let services = ServiceFlags(u64::decode(reader)?);
+            let timestamp = u64::decode(reader)?;
+            let addr_recv = Address::decode(reader)?;
+
+            let mut addr_trans = Address::default();
+            let mut nonce = 0;
+            let mut user_agent = String::new();
+            if version >= ADDR_FROM_VERSION {
+                addr_trans = Address::decode(reader)?;
+                nonce = u64::decode(reader)?;
+
+                let buf = encode::read_var_bytes(reader, MAX_USER_AGENT_LENGTH)?;
+                user_agent = String::from_utf8(buf)?;
+            }
+
+            let mut start_height = 0;
+            if version >= START_HEIGHT_VERSION {
+                start_height = i32::decode(reader)?;
+            }
+
+            // Like Bitcoin Core, tolerate peers that leave it out even with a recent version
+            let mut relay = true;
+            if version >= RELAY_VERSION {
+                let mut buf = [0u8; 1];
+                if reader.read(&mut buf)? == 1 {
+                    relay = match buf[0] {
+                        0 => false,
+                        1 => true,
+                        _ => {
+                            return Err(DeserializeError::new(ErrorKind::InvalidValue {
+                                field: "relay",
+                            }))
+                        }
+                    };
+                }
             }
-        };
 
-        Ok(Self {
-            version,
-            services,
-            timestamp,
-            addr_recv,
-            addr_trans,
-            nonce,
-            user_agent,
-            start_height,
-            relay,
+            Ok(Self {
+                version,
+                services,
+                timestamp,
+                addr_recv,
+                addr_trans,
+                nonce,
+                user_agent,
+                start_height,
+                relay,
+            })
         })
     }
 }
@@ -104,15 +123,15 @@ mod tests {
     fn test_version_serialize() {
         let version = Version {
             version: 70004,
-            services: 4,
+            services: ServiceFlags::BLOOM,
             timestamp: 1667596120,
             addr_recv: Address {
-                services: 1,
+                services: ServiceFlags::NETWORK,
                 ip: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1],
                 port: 0,
             },
             addr_trans: Address {
-                services: 1,
+                services: ServiceFlags::NETWORK,
                 ip: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1],
                 port: 0,
             },
@@ -144,15 +163,15 @@ mod tests {
             .unwrap(),
             Version {
                 version: 70004,
-                services: 4,
+                services: ServiceFlags::BLOOM,
                 timestamp: 1667596120,
                 addr_recv: Address {
-                    services: 1,
+                    services: ServiceFlags::NETWORK,
                     ip: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1],
                     port: 0,
                 },
                 addr_trans: Address {
-                    services: 1,
+                    services: ServiceFlags::NETWORK,
                     ip: [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 255, 255, 127, 0, 0, 1],
                     port: 0,
                 },
@@ -163,4 +182,74 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn test_version_old_protocol() {
+        let version = Version {
+            version: 60002,
+            services: ServiceFlags::NETWORK,
+            timestamp: 1667596120,
+            addr_recv: Address::default(),
+            addr_trans: Address::default(),
+            nonce: 1,
+            user_agent: "/Satoshi:0.7.2/".to_owned(),
+            start_height: -1,
+            relay: true,
+        };
+        let raw = version.serialize();
+
+        // No relay byte before 70001
+        assert_eq!(raw.len(), 4 + 8 + 8 + 26 + 26 + 8 + 16 + 4);
+        assert_eq!(Version::deserialize(&raw).unwrap(), version);
+
+        // Only addr_recv before 106
+        let version = Version {
+            version: 105,
+            nonce: 0,
+            user_agent: String::new(),
+            start_height: 0,
+            ..version
+        };
+        let raw = version.serialize();
+        assert_eq!(raw.len(), 4 + 8 + 8 + 26);
+        assert_eq!(Version::deserialize(&raw).unwrap(), version);
+    }
+
+    #[test]
+    fn test_version_missing_relay() {
+        let version = Version {
+            version: 70015,
+            services: ServiceFlags::NETWORK,
+            timestamp: 1667596120,
+            addr_recv: Address::default(),
+            addr_trans: Address::default(),
+            nonce: 1,
+            user_agent: String::new(),
+            start_height: 0,
+            relay: false,
+        };
+        let raw = version.serialize();
+
+        let version = Version::deserialize(&raw[..raw.len() - 1]).unwrap();
+        assert!(version.relay);
+    }
+
+    #[test]
+    fn test_version_user_agent_too_long() {
+        let mut version = Version {
+            version: 70015,
+            services: ServiceFlags::NETWORK,
+            timestamp: 1667596120,
+            addr_recv: Address::default(),
+            addr_trans: Address::default(),
+            nonce: 1,
+            user_agent: "a".repeat(256),
+            start_height: 0,
+            relay: false,
+        };
+        assert!(Version::deserialize(&version.serialize()).is_ok());
+
+        version.user_agent.push('a');
+        assert!(Version::deserialize(&version.serialize()).is_err());
+    }
 }